@@ -0,0 +1,19 @@
+import { op_host_buffer_give, op_host_buffer_take } from "ext:core/ops";
+
+/**
+ * Takes the buffer the host queued under `id`. The returned `Uint8Array`
+ * owns the host's allocation, nothing is copied. Each id can be taken once.
+ */
+function takeBuffer(id) {
+  return op_host_buffer_take(id);
+}
+
+/**
+ * Hands `data` to the host without copying it and returns the id the host
+ * receives it with. `data` must not be modified afterwards.
+ */
+function giveBuffer(data) {
+  return op_host_buffer_give(data);
+}
+
+export { giveBuffer, takeBuffer };
@@ -0,0 +1,8 @@
+import { op_mach_version } from "ext:core/ops";
+
+/** Version of the host running this plugin. */
+function version() {
+  return op_mach_version();
+}
+
+export { version };
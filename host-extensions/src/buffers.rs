@@ -0,0 +1,137 @@
+//! Buffers passed between the host and JS without copying their contents,
+//! exposed as `takeBuffer` and `giveBuffer` on the host namespace.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use bytes::Bytes;
+use deno_core::error::custom_error;
+use deno_core::error::AnyError;
+use deno_core::include_js_files;
+use deno_core::op2;
+use deno_core::JsBuffer;
+use deno_core::OpState;
+use deno_core::ToJsBuffer;
+
+use crate::HostExtension;
+
+/// Buffers passed between the host and JS without copying their contents.
+///
+/// A buffer sent to JS becomes the backing store of the `Uint8Array` that
+/// `takeBuffer` returns, so the allocation moves into V8 instead of being
+/// serialized. A `Uint8Array` given back through `giveBuffer` is kept as a
+/// `JsBuffer`, which borrows the V8 backing store rather than copying it
+/// out.
+///
+/// The host puts a clone into the runtime's `OpState`, so ids handed out on
+/// one side can be redeemed on the other.
+#[derive(Clone, Default)]
+pub struct HostBuffers(Rc<RefCell<BufferTable>>);
+
+#[derive(Default)]
+struct BufferTable {
+  next_id: u32,
+  to_js: HashMap<u32, ToJsBuffer>,
+  from_js: HashMap<u32, JsBuffer>,
+}
+
+/// The largest id handed out. Ids are passed to and from JS as smis, so they
+/// have to fit into an `i32`.
+const MAX_ID: u32 = i32::MAX as u32;
+
+impl BufferTable {
+  /// The next id that no pending buffer holds, wrapping around after
+  /// `MAX_ID`.
+  fn next_id(&mut self) -> u32 {
+    loop {
+      let id = self.next_id;
+      self.next_id = if id >= MAX_ID { 0 } else { id + 1 };
+      if !self.to_js.contains_key(&id) && !self.from_js.contains_key(&id) {
+        return id;
+      }
+    }
+  }
+}
+
+impl HostBuffers {
+  /// Queues `data` for JS and returns the id to take it with.
+  ///
+  /// V8 takes ownership of exactly `data.len()` bytes, so a `Vec` with spare
+  /// capacity is shrunk first, which may copy it. A `Box<[u8]>` is never
+  /// copied.
+  pub fn send(&self, data: impl Into<Box<[u8]>>) -> u32 {
+    let mut table = self.0.borrow_mut();
+    let id = table.next_id();
+    table.to_js.insert(id, ToJsBuffer::from(data.into()));
+    id
+  }
+
+  /// Like `send`. The contents are copied if `data` shares its allocation
+  /// with other `Bytes` or is a slice of a larger one.
+  pub fn send_bytes(&self, data: Bytes) -> u32 {
+    self.send(Vec::from(data))
+  }
+
+  /// Takes a buffer that JS gave back with `giveBuffer`.
+  pub fn receive(&self, id: u32) -> Option<JsBuffer> {
+    self.0.borrow_mut().from_js.remove(&id)
+  }
+}
+
+pub fn extension(namespace: &'static str) -> HostExtension {
+  HostExtension::new("buffers")
+    .ops([op_host_buffer_take::DECL, op_host_buffer_give::DECL])
+    .esm(
+      include_js_files!(buffers dir "js", "buffers.js",),
+      "ext:buffers/buffers.js",
+    )
+    .namespace(namespace)
+}
+
+fn host_buffers(state: &OpState) -> Result<&HostBuffers, AnyError> {
+  state.try_borrow::<HostBuffers>().ok_or_else(|| {
+    custom_error("NotSupported", "Host buffers are not available here")
+  })
+}
+
+#[op2]
+#[serde]
+fn op_host_buffer_take(
+  state: &mut OpState,
+  #[smi] id: u32,
+) -> Result<ToJsBuffer, AnyError> {
+  let buffer = host_buffers(state)?.0.borrow_mut().to_js.remove(&id);
+  buffer.ok_or_else(|| {
+    custom_error("NotFound", format!("No host buffer with id {id}"))
+  })
+}
+
+#[op2]
+#[smi]
+fn op_host_buffer_give(
+  state: &mut OpState,
+  #[buffer] data: JsBuffer,
+) -> Result<u32, AnyError> {
+  let mut table = host_buffers(state)?.0.borrow_mut();
+  let id = table.next_id();
+  table.from_js.insert(id, data);
+  Ok(id)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn ids_wrap_within_i32_and_skip_pending_buffers() {
+    let buffers = HostBuffers::default();
+    assert_eq!(buffers.send(vec![0]), 0);
+    assert_eq!(buffers.send(vec![1]), 1);
+    buffers.0.borrow_mut().to_js.remove(&1);
+    buffers.0.borrow_mut().next_id = MAX_ID;
+    assert_eq!(buffers.send(vec![2]), MAX_ID);
+    assert_eq!(buffers.send(vec![3]), 1);
+    assert_eq!(buffers.send(vec![4]), 2);
+  }
+}
@@ -0,0 +1,137 @@
+//! A clock that replaces the wall clock as seen from JS.
+//!
+//! When a `VirtualClock` is put into the `OpState`, `js/99_main.js` makes
+//! `Date.now()`, `new Date()` and `performance.now()` read it instead of the
+//! system time, so that repeated runs observe the same times. The main
+//! runtime's `setTimeout` and `setInterval` are replaced as well: their
+//! timers only fire when the host moves the clock and calls
+//! `run_due_timers`.
+
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+use deno_core::error::generic_error;
+use deno_core::error::AnyError;
+use deno_core::error::JsError;
+use deno_core::op2;
+use deno_core::serde_v8;
+use deno_core::v8;
+use deno_core::JsRuntime;
+use deno_core::OpState;
+use serde::Deserialize;
+
+use crate::HostExtension;
+
+/// Milliseconds since the Unix epoch, shared by every clone.
+///
+/// The clock only moves when advanced, so a clone kept by the host controls
+/// the time seen by every runtime it was put into.
+#[derive(Clone, Debug)]
+pub struct VirtualClock(Arc<AtomicU64>);
+
+impl VirtualClock {
+  /// A clock standing still at `epoch_ms`.
+  pub fn new(epoch_ms: f64) -> Self {
+    Self(Arc::new(AtomicU64::new(epoch_ms.to_bits())))
+  }
+
+  pub fn now(&self) -> f64 {
+    f64::from_bits(self.0.load(Ordering::SeqCst))
+  }
+
+  /// Moves the clock forward without firing any timers.
+  pub fn advance(&self, duration: Duration) {
+    self.advance_to(self.now() + duration.as_secs_f64() * 1000.0);
+  }
+
+  /// Moves the clock forward to `epoch_ms`. Never moves it backwards.
+  pub fn advance_to(&self, epoch_ms: f64) {
+    let _ = self.0.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |now| {
+      (epoch_ms > f64::from_bits(now)).then_some(epoch_ms.to_bits())
+    });
+  }
+}
+
+/// `runDueTimers` of `99_main.js`, registered while bootstrapping.
+struct VirtualTimers(v8::Global<v8::Function>);
+
+#[derive(Debug, Deserialize)]
+pub struct DueTimers {
+  /// Whether a timer fired. Its callback may have started async work, so
+  /// run the event loop before calling `run_due_timers` again.
+  pub fired: bool,
+  /// When the next timer is due, if none fired.
+  pub next: Option<f64>,
+}
+
+/// Fires the earliest timer of `js_runtime` if it is due at the current
+/// time of the clock. Does nothing for runtimes without virtual timers.
+pub fn run_due_timers(
+  js_runtime: &mut JsRuntime,
+) -> Result<DueTimers, AnyError> {
+  let op_state = js_runtime.op_state();
+  let run = match op_state.borrow().try_borrow::<VirtualTimers>() {
+    Some(VirtualTimers(run)) => run.clone(),
+    None => {
+      return Ok(DueTimers {
+        fired: false,
+        next: None,
+      })
+    }
+  };
+  let scope = &mut js_runtime.handle_scope();
+  let run = v8::Local::new(scope, run);
+  let undefined = v8::undefined(scope);
+  let scope = &mut v8::TryCatch::new(scope);
+  let result = run.call(scope, undefined.into(), &[]);
+  if let Some(exception) = scope.exception() {
+    return Err(JsError::from_v8_exception(scope, exception).into());
+  }
+  let result = result.ok_or_else(|| generic_error("Execution terminated"))?;
+  Ok(serde_v8::from_v8(scope, result)?)
+}
+
+pub fn extension() -> HostExtension {
+  HostExtension::new("virtual_clock").ops([
+    op_virtual_clock_enabled::DECL,
+    op_virtual_clock_now::DECL,
+    op_virtual_clock_set_timers::DECL,
+  ])
+}
+
+#[op2(fast)]
+fn op_virtual_clock_enabled(state: &mut OpState) -> bool {
+  state.has::<VirtualClock>()
+}
+
+#[op2(fast)]
+fn op_virtual_clock_now(state: &mut OpState) -> f64 {
+  state.borrow::<VirtualClock>().now()
+}
+
+#[op2]
+fn op_virtual_clock_set_timers(
+  state: &mut OpState,
+  #[global] run_due_timers: v8::Global<v8::Function>,
+) {
+  state.put(VirtualTimers(run_due_timers));
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn clock_only_moves_forward_and_is_shared_by_clones() {
+    let clock = VirtualClock::new(1_000.0);
+    let clone = clock.clone();
+    clock.advance(Duration::from_millis(250));
+    assert_eq!(clone.now(), 1_250.0);
+    clone.advance_to(1_100.0);
+    assert_eq!(clock.now(), 1_250.0);
+    clone.advance_to(2_000.0);
+    assert_eq!(clock.now(), 2_000.0);
+  }
+}
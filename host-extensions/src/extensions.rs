@@ -0,0 +1,582 @@
+//! The ordered list of extensions the snapshot is created with and every
+//! runtime created from it registers.
+//!
+//! `three` calls `extensions` with `ExtensionMode::ForSnapshot` and
+//! `pass-no-snapshot` with `ExtensionMode::FromSnapshot`. The snapshot
+//! records the extensions it was created with, and
+//! `check_snapshot_extensions` compares them to the runtime's list before any
+//! user code runs. Web workers are built by `deno_runtime` from its own list,
+//! so their ops are compared one by one with `check_snapshot_ops` instead.
+//!
+//! Extensions behind a cargo feature (`ffi`, `napi`, `webgpu`, `kv`, `cron`
+//! and `node`) are replaced by a stub when the feature is disabled. The stub
+//! has the same name, so `deps` of other extensions are still satisfied, no
+//! ops, and empty versions of the modules the runtime JS imports from it.
+//! Web workers register the real extension's ops all the same, which
+//! `check_snapshot_ops` skips.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use deno_cache::SqliteBackedCache;
+use deno_core::error::generic_error;
+use deno_core::error::AnyError;
+use deno_core::located_script_name;
+use deno_core::serde_v8;
+use deno_core::url::Url;
+use deno_core::v8;
+use deno_core::Extension;
+use deno_core::ExtensionFileSource;
+use deno_core::FastString;
+use deno_core::JsRuntime;
+use deno_core::OpDecl;
+use deno_http::DefaultHttpPropertyExtractor;
+use deno_runtime::ops;
+use deno_runtime::ops::worker_host::CreateWebWorkerCb;
+use deno_runtime::worker::FormatJsErrorFn;
+use deno_web::BlobStore;
+
+use crate::HostExtension;
+use crate::HostState;
+
+const MANIFEST_EXTENSION: &str = "host_extensions_manifest";
+const MANIFEST_SPECIFIER: &str = "ext:host_extensions_manifest/manifest.js";
+const MANIFEST_KEY: &str = "host_extensions.snapshot";
+const OPS_KEY: &str = "host_extensions.snapshot_ops";
+const NAMESPACES_EXTENSION: &str = "host_namespaces";
+const NAMESPACES_SPECIFIER: &str = "ext:host_namespaces/namespaces.js";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExtensionMode {
+  /// Ops and JS, for creating the snapshot.
+  ForSnapshot,
+  /// Ops only, for a runtime whose JS comes from the snapshot.
+  FromSnapshot,
+}
+
+/// Declares a permission trait that requires `$bound` when `$feature` is
+/// enabled and nothing otherwise, so `ExtensionPermissions` only asks for
+/// the permissions of extensions that are compiled in.
+macro_rules! feature_permissions {
+  ($name:ident, $feature:literal, $bound:path) => {
+    #[cfg(feature = $feature)]
+    pub trait $name: $bound {}
+    #[cfg(feature = $feature)]
+    impl<P: $bound> $name for P {}
+    #[cfg(not(feature = $feature))]
+    pub trait $name {}
+    #[cfg(not(feature = $feature))]
+    impl<P> $name for P {}
+  };
+}
+
+feature_permissions!(FfiPermissions, "ffi", deno_ffi::FfiPermissions);
+feature_permissions!(NapiPermissions, "napi", deno_napi::NapiPermissions);
+feature_permissions!(
+  KvPermissions,
+  "kv",
+  deno_kv::sqlite::SqliteDbHandlerPermissions
+);
+feature_permissions!(NodePermissions, "node", deno_node::NodePermissions);
+
+/// Every permission trait required by the extensions in the list.
+pub trait ExtensionPermissions:
+  deno_web::TimersPermission
+  + deno_fetch::FetchPermissions
+  + deno_websocket::WebSocketPermissions
+  + deno_net::NetPermissions
+  + deno_fs::FsPermissions
+  + FfiPermissions
+  + NapiPermissions
+  + KvPermissions
+  + NodePermissions
+  + 'static
+{
+}
+
+impl<P> ExtensionPermissions for P where
+  P: deno_web::TimersPermission
+    + deno_fetch::FetchPermissions
+    + deno_websocket::WebSocketPermissions
+    + deno_net::NetPermissions
+    + deno_fs::FsPermissions
+    + FfiPermissions
+    + NapiPermissions
+    + KvPermissions
+    + NodePermissions
+    + 'static
+{
+}
+
+/// State the extensions are initialized with. The defaults are enough for
+/// creating the snapshot.
+pub struct ExtensionOptions {
+  pub main_module: Url,
+  pub blob_store: Arc<BlobStore>,
+  pub broadcast_channel: deno_broadcast_channel::InMemoryBroadcastChannel,
+  pub fs: Arc<dyn deno_fs::FileSystem>,
+  pub create_web_worker_cb: Arc<CreateWebWorkerCb>,
+  pub format_js_error_fn: Option<Arc<FormatJsErrorFn>>,
+  pub host_state: HostState,
+  /// Seeds the RNG behind `crypto.getRandomValues()` and `randomUUID()`.
+  pub seed: Option<u64>,
+  /// The `runtime` extension with the bootstrap JS. Defaults to the one of
+  /// `deno_runtime`; `three` supplies its own from `js/`.
+  pub runtime: Option<Extension>,
+  /// The global the host extensions' JS is exposed under. Only used while
+  /// creating the snapshot.
+  pub namespace: &'static str,
+}
+
+impl Default for ExtensionOptions {
+  fn default() -> Self {
+    Self {
+      main_module: "deno:runtime".parse().unwrap(),
+      blob_store: Default::default(),
+      broadcast_channel: Default::default(),
+      fs: Arc::new(deno_fs::RealFs),
+      create_web_worker_cb: Arc::new(|_| {
+        unreachable!("not used in snapshot.")
+      }),
+      format_js_error_fn: None,
+      host_state: Default::default(),
+      seed: None,
+      runtime: None,
+      namespace: crate::DEFAULT_NAMESPACE,
+    }
+  }
+}
+
+macro_rules! init {
+  ($mode:expr, $krate:ident::$ext:ident $(::<$ty:ty>)? ($($arg:expr),* $(,)?)) => {
+    match $mode {
+      ExtensionMode::ForSnapshot => {
+        $krate::$ext::init_ops_and_esm$(::<$ty>)?($($arg),*)
+      }
+      ExtensionMode::FromSnapshot => {
+        $krate::$ext::init_ops$(::<$ty>)?($($arg),*)
+      }
+    }
+  };
+}
+
+/// The canonical extension list. Both modes produce the same extensions in
+/// the same order, only `ForSnapshot` includes their JS.
+pub fn extensions<P: ExtensionPermissions>(
+  mode: ExtensionMode,
+  options: ExtensionOptions,
+) -> Vec<Extension> {
+  let ExtensionOptions {
+    main_module,
+    blob_store,
+    broadcast_channel,
+    fs,
+    create_web_worker_cb,
+    format_js_error_fn,
+    host_state,
+    seed,
+    runtime,
+    namespace,
+  } = options;
+
+  let mut extensions = vec![
+    init!(mode, deno_webidl::deno_webidl()),
+    init!(mode, deno_console::deno_console()),
+    init!(mode, deno_url::deno_url()),
+    init!(mode, deno_web::deno_web::<P>(blob_store, None)),
+    webgpu(mode),
+    canvas(mode),
+    init!(mode, deno_fetch::deno_fetch::<P>(Default::default())),
+    init!(mode, deno_cache::deno_cache::<SqliteBackedCache>(None)),
+    init!(
+      mode,
+      deno_websocket::deno_websocket::<P>("".to_owned(), None, None)
+    ),
+    init!(mode, deno_webstorage::deno_webstorage(None)),
+    init!(mode, deno_crypto::deno_crypto(seed)),
+    init!(
+      mode,
+      deno_broadcast_channel::deno_broadcast_channel(broadcast_channel)
+    ),
+    ffi::<P>(mode),
+    init!(mode, deno_net::deno_net::<P>(None, None)),
+    init!(mode, deno_tls::deno_tls()),
+    kv::<P>(mode),
+    cron(mode),
+    napi::<P>(mode),
+    init!(mode, deno_http::deno_http::<DefaultHttpPropertyExtractor>()),
+    init!(mode, deno_io::deno_io(Default::default())),
+    init!(mode, deno_fs::deno_fs::<P>(fs.clone())),
+    node::<P>(mode, fs),
+    ops::runtime::deno_runtime::init_ops(main_module),
+    ops::worker_host::deno_worker_host::init_ops(
+      create_web_worker_cb,
+      format_js_error_fn,
+    ),
+    ops::fs_events::deno_fs_events::init_ops(),
+    ops::os::deno_os::init_ops(Default::default()),
+    ops::permissions::deno_permissions::init_ops(),
+    ops::process::deno_process::init_ops(),
+    ops::signal::deno_signal::init_ops(),
+    ops::tty::deno_tty::init_ops(),
+    ops::http::deno_http_runtime::init_ops(),
+    ops::bootstrap::deno_bootstrap::init_ops(None),
+    runtime.unwrap_or_else(|| init!(mode, deno_runtime::runtime())),
+    ops::web_worker::deno_web_worker::init_ops(),
+    host_state.extension(),
+  ];
+
+  extensions.extend(crate::op_overrides());
+  let host_extensions = crate::host_extensions(namespace);
+  let namespaces = namespaces_extension(mode, &host_extensions);
+  extensions.extend(host_extensions.into_iter().map(match mode {
+    ExtensionMode::ForSnapshot => HostExtension::for_snapshot,
+    ExtensionMode::FromSnapshot => HostExtension::for_runtime,
+  }));
+  extensions.push(namespaces);
+
+  let manifest = extension_manifest(&extensions);
+  let ops = op_manifest(&extensions);
+  extensions.push(manifest_extension(mode, &manifest, &ops));
+  extensions
+}
+
+#[cfg(feature = "ffi")]
+fn ffi<P: ExtensionPermissions>(mode: ExtensionMode) -> Extension {
+  init!(mode, deno_ffi::deno_ffi::<P>())
+}
+
+#[cfg(not(feature = "ffi"))]
+fn ffi<P>(mode: ExtensionMode) -> Extension {
+  stub(mode, "deno_ffi", &[("ext:deno_ffi/00_ffi.js", "export {};\n")])
+}
+
+#[cfg(feature = "napi")]
+fn napi<P: ExtensionPermissions>(mode: ExtensionMode) -> Extension {
+  init!(mode, deno_napi::deno_napi::<P>())
+}
+
+#[cfg(not(feature = "napi"))]
+fn napi<P>(mode: ExtensionMode) -> Extension {
+  stub(mode, "deno_napi", &[])
+}
+
+#[cfg(feature = "webgpu")]
+fn webgpu(mode: ExtensionMode) -> Extension {
+  init!(mode, deno_webgpu::deno_webgpu())
+}
+
+#[cfg(not(feature = "webgpu"))]
+fn webgpu(mode: ExtensionMode) -> Extension {
+  stub(
+    mode,
+    "deno_webgpu",
+    &[
+      (
+        "ext:deno_webgpu/00_init.js",
+        "export function loadWebGPU() {\n  throw new TypeError(\"WebGPU is not available in this build\");\n}\n",
+      ),
+      ("ext:deno_webgpu/02_surface.js", "export {};\n"),
+    ],
+  )
+}
+
+#[cfg(feature = "webgpu")]
+fn canvas(mode: ExtensionMode) -> Extension {
+  init!(mode, deno_canvas::deno_canvas())
+}
+
+#[cfg(not(feature = "webgpu"))]
+fn canvas(mode: ExtensionMode) -> Extension {
+  stub(mode, "deno_canvas", &[])
+}
+
+#[cfg(feature = "kv")]
+fn kv<P: ExtensionPermissions>(mode: ExtensionMode) -> Extension {
+  init!(
+    mode,
+    deno_kv::deno_kv(deno_kv::sqlite::SqliteDbHandler::<P>::new(None, None))
+  )
+}
+
+#[cfg(not(feature = "kv"))]
+fn kv<P>(mode: ExtensionMode) -> Extension {
+  stub(mode, "deno_kv", &[("ext:deno_kv/01_db.ts", "export {};\n")])
+}
+
+#[cfg(feature = "cron")]
+fn cron(mode: ExtensionMode) -> Extension {
+  init!(
+    mode,
+    deno_cron::deno_cron(deno_cron::local::LocalCronHandler::new())
+  )
+}
+
+#[cfg(not(feature = "cron"))]
+fn cron(mode: ExtensionMode) -> Extension {
+  stub(mode, "deno_cron", &[("ext:deno_cron/01_cron.ts", "export {};\n")])
+}
+
+#[cfg(feature = "node")]
+fn node<P: ExtensionPermissions>(
+  mode: ExtensionMode,
+  fs: Arc<dyn deno_fs::FileSystem>,
+) -> Extension {
+  init!(mode, deno_node::deno_node::<P>(None, fs))
+}
+
+#[cfg(not(feature = "node"))]
+fn node<P>(mode: ExtensionMode, _fs: Arc<dyn deno_fs::FileSystem>) -> Extension {
+  stub(mode, "deno_node", &[])
+}
+
+/// Stands in for an extension whose feature is disabled. `modules` are the
+/// specifiers other extensions import from it, with their replacement code.
+#[cfg(not(all(
+  feature = "ffi",
+  feature = "napi",
+  feature = "webgpu",
+  feature = "kv",
+  feature = "cron",
+  feature = "node"
+)))]
+fn stub(
+  mode: ExtensionMode,
+  name: &'static str,
+  modules: &[(&'static str, &'static str)],
+) -> Extension {
+  let esm_files = match mode {
+    ExtensionMode::ForSnapshot => modules
+      .iter()
+      .map(|&(specifier, code)| {
+        ExtensionFileSource::new_computed(specifier, code.into())
+      })
+      .collect(),
+    ExtensionMode::FromSnapshot => vec![],
+  };
+  Extension {
+    name,
+    esm_files: esm_files.into(),
+    ..Default::default()
+  }
+}
+
+/// Identifies `extensions` in order, without the manifest. Each entry is
+/// the name and op count, so a stub and the extension it replaces differ.
+pub fn extension_manifest(extensions: &[Extension]) -> Vec<String> {
+  extensions
+    .iter()
+    .filter(|ext| ext.name != MANIFEST_EXTENSION)
+    .map(|ext| format!("{}/{}", ext.name, ext.ops.len()))
+    .collect()
+}
+
+/// The ops of `extensions` by name, in registration order.
+pub fn op_manifest(extensions: &[Extension]) -> Vec<&'static str> {
+  extensions
+    .iter()
+    .flat_map(|ext| ext.ops.iter().map(|op| op.name))
+    .collect()
+}
+
+/// The ops of the extensions that are stubbed out because their feature is
+/// disabled. `deno_runtime` depends on every extension crate regardless of
+/// these features and registers their ops in its web workers anyway.
+fn stubbed_ops() -> Vec<&'static str> {
+  use deno_runtime::deno_canvas;
+  use deno_runtime::deno_cron;
+  use deno_runtime::deno_ffi;
+  use deno_runtime::deno_kv;
+  use deno_runtime::deno_napi;
+  use deno_runtime::deno_node;
+  use deno_runtime::deno_webgpu;
+  use deno_runtime::permissions::PermissionsContainer as P;
+  let fs: Arc<dyn deno_fs::FileSystem> = Arc::new(deno_fs::RealFs);
+  let stubbed = [
+    (!cfg!(feature = "webgpu")).then(deno_webgpu::deno_webgpu::init_ops),
+    (!cfg!(feature = "webgpu")).then(deno_canvas::deno_canvas::init_ops),
+    (!cfg!(feature = "ffi")).then(deno_ffi::deno_ffi::init_ops::<P>),
+    (!cfg!(feature = "kv")).then(|| {
+      let handler = deno_kv::sqlite::SqliteDbHandler::<P>::new(None, None);
+      deno_kv::deno_kv::init_ops(handler)
+    }),
+    (!cfg!(feature = "cron")).then(|| {
+      deno_cron::deno_cron::init_ops(deno_cron::local::LocalCronHandler::new())
+    }),
+    (!cfg!(feature = "napi")).then(deno_napi::deno_napi::init_ops::<P>),
+    (!cfg!(feature = "node"))
+      .then(|| deno_node::deno_node::init_ops::<P>(None, fs)),
+  ];
+  op_manifest(&stubbed.into_iter().flatten().collect::<Vec<_>>())
+}
+
+/// An extension without ops whose middleware records the name of every op
+/// the runtime registers, in order, for `check_snapshot_ops`.
+pub fn op_recorder() -> (Extension, Rc<RefCell<Vec<&'static str>>>) {
+  let ops = Rc::new(RefCell::new(vec![]));
+  let recorded = ops.clone();
+  let extension = Extension {
+    name: "host_op_recorder",
+    middleware_fn: Some(Box::new(move |op: OpDecl| {
+      recorded.borrow_mut().push(op.name);
+      op
+    })),
+    ..Default::default()
+  };
+  (extension, ops)
+}
+
+/// Records `manifest` and `ops` on the snapshot's global object under
+/// symbols.
+fn manifest_extension(
+  mode: ExtensionMode,
+  manifest: &[String],
+  ops: &[&str],
+) -> Extension {
+  let esm_files = match mode {
+    ExtensionMode::ForSnapshot => {
+      let code = define_frozen_array(MANIFEST_KEY, manifest)
+        + &define_frozen_array(OPS_KEY, ops);
+      vec![ExtensionFileSource::new_computed(
+        MANIFEST_SPECIFIER,
+        code.into(),
+      )]
+    }
+    ExtensionMode::FromSnapshot => vec![],
+  };
+  Extension {
+    name: MANIFEST_EXTENSION,
+    esm_entry_point: (mode == ExtensionMode::ForSnapshot)
+      .then_some(MANIFEST_SPECIFIER),
+    esm_files: esm_files.into(),
+    ..Default::default()
+  }
+}
+
+fn define_frozen_array(key: &str, entries: &[impl AsRef<str>]) -> String {
+  let entries = entries
+    .iter()
+    .map(|entry| format!("\"{}\"", entry.as_ref()))
+    .collect::<Vec<_>>()
+    .join(", ");
+  format!(
+    "Object.defineProperty(globalThis, Symbol.for(\"{key}\"), {{\n  value: Object.freeze([{entries}]),\n}});\n"
+  )
+}
+
+/// Installs the namespaces of the host extensions, see `namespaces_module`.
+fn namespaces_extension(
+  mode: ExtensionMode,
+  host_extensions: &[HostExtension],
+) -> Extension {
+  let esm_files = match mode {
+    ExtensionMode::ForSnapshot => vec![ExtensionFileSource::new_computed(
+      NAMESPACES_SPECIFIER,
+      crate::namespaces_module(host_extensions).into(),
+    )],
+    ExtensionMode::FromSnapshot => vec![],
+  };
+  Extension {
+    name: NAMESPACES_EXTENSION,
+    esm_entry_point: (mode == ExtensionMode::ForSnapshot)
+      .then_some(NAMESPACES_SPECIFIER),
+    esm_files: esm_files.into(),
+    ..Default::default()
+  }
+}
+
+/// Fails if the snapshot `js_runtime` was created from was made with a
+/// different extension list than `extensions`, which would leave ops and the
+/// JS that calls them out of step.
+pub fn check_snapshot_extensions(
+  js_runtime: &mut JsRuntime,
+  extensions: &[String],
+) -> Result<(), AnyError> {
+  let snapshot = read_manifest(js_runtime, MANIFEST_KEY)?;
+  if snapshot != extensions {
+    return Err(generic_error(format!(
+      "Snapshot was created with extensions [{}] but the runtime registers [{}], recreate it with `three`",
+      snapshot.join(", "),
+      extensions.join(", "),
+    )));
+  }
+  Ok(())
+}
+
+/// Like `check_snapshot_extensions`, for runtimes that are not built from
+/// `extensions`, such as `deno_runtime`'s web workers. Compares the ops
+/// recorded with `op_recorder` to the ops the snapshot was created with,
+/// skipping those of extensions that are stubbed out here.
+pub fn check_snapshot_ops(
+  js_runtime: &mut JsRuntime,
+  ops: &[&str],
+) -> Result<(), AnyError> {
+  let snapshot = read_manifest(js_runtime, OPS_KEY)?;
+  let stubbed = stubbed_ops();
+  let ops = ops
+    .iter()
+    .copied()
+    .filter(|op| !stubbed.contains(op))
+    .collect::<Vec<_>>();
+  let mismatch = (0..snapshot.len().max(ops.len()))
+    .find(|&i| snapshot.get(i).map(String::as_str) != ops.get(i).copied());
+  if let Some(i) = mismatch {
+    return Err(generic_error(format!(
+      "Op #{i} of the snapshot is {} but the runtime registers {}, recreate the snapshot with `three`",
+      snapshot.get(i).map_or("missing", String::as_str),
+      ops.get(i).copied().unwrap_or("none"),
+    )));
+  }
+  Ok(())
+}
+
+fn read_manifest(
+  js_runtime: &mut JsRuntime,
+  key: &str,
+) -> Result<Vec<String>, AnyError> {
+  let manifest = js_runtime.execute_script(
+    located_script_name!(),
+    FastString::from(format!("globalThis[Symbol.for(\"{key}\")]")),
+  )?;
+  let scope = &mut js_runtime.handle_scope();
+  let manifest = v8::Local::new(scope, manifest);
+  let manifest: Option<Vec<String>> = serde_v8::from_v8(scope, manifest)?;
+  manifest.ok_or_else(|| {
+    generic_error(
+      "Snapshot has no extension manifest, recreate it with `three`",
+    )
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use deno_core::RuntimeOptions;
+
+  #[test]
+  fn stubbed_ops_are_those_of_disabled_features() {
+    let stubbed = stubbed_ops();
+    assert_eq!(stubbed.contains(&"op_ffi_load"), cfg!(not(feature = "ffi")));
+    assert_eq!(
+      stubbed.contains(&"op_kv_database_open"),
+      cfg!(not(feature = "kv"))
+    );
+    assert!(!stubbed.contains(&"op_fetch"));
+  }
+
+  #[cfg(not(feature = "ffi"))]
+  #[test]
+  fn check_snapshot_ops_skips_ops_of_stubbed_extensions() {
+    let manifest =
+      manifest_extension(ExtensionMode::ForSnapshot, &[], &["op_a", "op_b"]);
+    let mut js_runtime = JsRuntime::new(RuntimeOptions {
+      extensions: vec![manifest],
+      ..Default::default()
+    });
+    let ops = ["op_a", "op_ffi_load", "op_b"];
+    assert!(check_snapshot_ops(&mut js_runtime, &ops).is_ok());
+    let ops = ["op_a", "op_other", "op_b"];
+    assert!(check_snapshot_ops(&mut js_runtime, &ops).is_err());
+  }
+}
@@ -0,0 +1,972 @@
+// Copyright 2018-2024 the Deno authors. All rights reserved. MIT license.
+
+//! `Deno.watchFs()` with debouncing, coalescing, include and exclude globs
+//! and a polling fallback.
+//!
+//! `deno_runtime` registers its own `op_fs_events_open` and
+//! `op_fs_events_poll`, which `js/40_fs_events.js` of `three` imports. The
+//! `host_fs_events` extension keeps their names and replaces their
+//! implementation through its middleware.
+
+use deno_core::error::type_error;
+use deno_core::error::AnyError;
+use deno_core::parking_lot::Mutex;
+use deno_core::CancelFuture;
+use deno_core::CancelHandle;
+use deno_core::OpState;
+use deno_core::RcRef;
+use deno_core::Resource;
+use deno_core::ResourceId;
+
+use deno_core::op2;
+use deno_runtime::permissions::PermissionsContainer;
+
+use glob::Pattern;
+use notify::event::AccessKind;
+use notify::event::CreateKind;
+use notify::event::Event as NotifyEvent;
+use notify::event::ModifyKind;
+use notify::event::RemoveKind;
+use notify::event::RenameMode;
+use notify::Config;
+use notify::Error as NotifyError;
+use notify::ErrorKind as NotifyErrorKind;
+use notify::EventKind;
+use notify::PollWatcher;
+use notify::RecommendedWatcher;
+use notify::RecursiveMode;
+use notify::Watcher;
+use serde::Deserialize;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::convert::From;
+use std::path::Path;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::mpsc as std_mpsc;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+use tokio::sync::Notify;
+
+/// Number of pending events after which they are replaced by a single
+/// rescan event, unless set with the `queueCapacity` option.
+const DEFAULT_QUEUE_CAPACITY: usize = 1024;
+
+/// How often a `PollWatcher` scans the watched paths, unless set with the
+/// `pollInterval` option.
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+deno_core::extension!(
+  host_fs_events,
+  middleware = |op| match op.name {
+    "op_fs_events_open" => {
+      op.with_implementation_from(&op_host_fs_events_open::DECL)
+    }
+    "op_fs_events_poll" => {
+      op.with_implementation_from(&op_host_fs_events_poll::DECL)
+    }
+    _ => op,
+  },
+);
+
+struct FsEventsResource {
+  #[allow(unused)]
+  watcher: Arc<Mutex<Box<dyn Watcher + Send>>>,
+  queue: Arc<EventQueue>,
+  cancel: CancelHandle,
+}
+
+impl Resource for FsEventsResource {
+  fn name(&self) -> Cow<str> {
+    "fsEvents".into()
+  }
+
+  fn close(self: Rc<Self>) {
+    self.cancel.cancel();
+  }
+}
+
+/// Represents a file system event.
+///
+/// We do not use the event directly from the notify crate. We flatten
+/// the structure into this simpler structure. We want to only make it more
+/// complex as needed.
+///
+/// Feel free to expand this struct as long as you can add tests to demonstrate
+/// the complexity.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct FsEvent {
+  kind: &'static str,
+  paths: Vec<PathBuf>,
+  flag: Option<&'static str>,
+  /// What changed, for `modify` (`data`, `metadata`, `name` or `other`) and
+  /// `access` (`read`, `open`, `close` or `other`) events.
+  detail: Option<&'static str>,
+  /// For `name` changes, whether `paths` holds the old path (`from`), the
+  /// new path (`to`) or both, in that order (`both`).
+  rename: Option<&'static str>,
+  /// `file`, `directory` or `symlink`, for the last path of the event. Taken
+  /// from the event where the OS reports it, otherwise from the path's
+  /// metadata unless it no longer exists.
+  file_type: Option<&'static str>,
+  /// Links the two halves of a rename reported separately.
+  #[serde(skip)]
+  tracker: Option<usize>,
+}
+
+impl FsEvent {
+  fn rescan() -> Self {
+    Self::flagged("rescan")
+  }
+
+  /// The first event of a watcher that had to fall back to polling because
+  /// the OS limit on watches was reached.
+  fn poll_fallback() -> Self {
+    Self::flagged("pollFallback")
+  }
+
+  fn flagged(flag: &'static str) -> Self {
+    FsEvent {
+      kind: "other",
+      paths: vec![],
+      flag: Some(flag),
+      detail: None,
+      rename: None,
+      file_type: None,
+      tracker: None,
+    }
+  }
+
+  /// Completes the `from` half of a rename with its `to` half, `other`.
+  /// Hands `other` back if it belongs to a different rename.
+  fn pair_rename(&mut self, other: FsEvent) -> Result<(), FsEvent> {
+    if self.rename != Some("from")
+      || other.rename != Some("to")
+      || self.tracker.is_none()
+      || self.tracker != other.tracker
+    {
+      return Err(other);
+    }
+    self.paths.extend(other.paths);
+    self.rename = Some("both");
+    self.file_type = other.file_type;
+    Ok(())
+  }
+
+  /// Merges `other`, a later event for the same single path, into this
+  /// one. The later kind wins, except that a created file stays created
+  /// when it is modified afterwards and that access does not hide changes.
+  /// Different kinds of modification merge into `detail: "other"`. Hands
+  /// `other` back if it is for a different path or either is a rename.
+  fn coalesce(&mut self, other: FsEvent) -> Result<(), FsEvent> {
+    if self.flag.is_some()
+      || other.flag.is_some()
+      || self.rename.is_some()
+      || other.rename.is_some()
+      || self.paths.len() != 1
+      || self.paths != other.paths
+    {
+      return Err(other);
+    }
+    (self.kind, self.detail) = match (self.kind, other.kind) {
+      ("create", "modify" | "access" | "any" | "other") => {
+        ("create", self.detail)
+      }
+      (kind, "access") => (kind, self.detail),
+      ("modify", "modify") if self.detail != other.detail => {
+        ("modify", Some("other"))
+      }
+      (_, kind) => (kind, other.detail),
+    };
+    self.file_type = other.file_type.or(self.file_type);
+    Ok(())
+  }
+}
+
+/// Events waiting to be polled, shared with the watcher thread.
+///
+/// An event for a path that already has one pending is coalesced into it.
+/// With a debounce window, nothing is delivered until no event has arrived
+/// for the length of the window. If `capacity` events are pending, they are
+/// dropped and replaced by a single rescan event, as the watcher would do
+/// when the OS queue overflows; events arriving before it is polled are
+/// covered by it and dropped as well.
+struct EventQueue {
+  pending: Mutex<PendingEvents>,
+  notify: Notify,
+  debounce: Option<Duration>,
+  capacity: usize,
+}
+
+struct PendingEvents {
+  events: VecDeque<Result<FsEvent, AnyError>>,
+  last_event: Option<Instant>,
+  overflowed: bool,
+}
+
+impl PendingEvents {
+  /// Whether the halves of the rename `event` have already been paired.
+  fn has_paired(&self, event: &FsEvent) -> bool {
+    self.events.iter().any(|pending| {
+      pending.as_ref().is_ok_and(|pending| {
+        pending.rename == Some("both") && pending.paths == event.paths
+      })
+    })
+  }
+}
+
+impl EventQueue {
+  fn new(debounce: Option<Duration>, capacity: usize) -> Self {
+    Self {
+      pending: Mutex::new(PendingEvents {
+        events: VecDeque::new(),
+        last_event: None,
+        overflowed: false,
+      }),
+      notify: Notify::new(),
+      debounce,
+      capacity: capacity.max(1),
+    }
+  }
+
+  fn push(&self, event: Result<FsEvent, AnyError>) {
+    let mut pending = self.pending.lock();
+    pending.last_event = Some(Instant::now());
+    if pending.overflowed {
+      return;
+    }
+    let event = match event {
+      Ok(mut event) => {
+        // Some backends report a rename both as separate halves and as a
+        // single event with both paths.
+        if event.rename == Some("both") && pending.has_paired(&event) {
+          return;
+        }
+        for queued in pending.events.iter_mut().filter_map(|e| e.as_mut().ok())
+        {
+          match queued
+            .pair_rename(event)
+            .or_else(|event| queued.coalesce(event))
+          {
+            Ok(()) => return,
+            Err(rejected) => event = rejected,
+          }
+        }
+        Ok(event)
+      }
+      Err(err) => Err(err),
+    };
+    if pending.events.len() >= self.capacity {
+      pending.events.clear();
+      pending.events.push_back(Ok(FsEvent::rescan()));
+      pending.overflowed = true;
+    } else {
+      pending.events.push_back(event);
+    }
+    drop(pending);
+    self.notify.notify_one();
+  }
+
+  /// Waits for the next event, and for the debounce window to pass without
+  /// further events.
+  async fn next(&self) -> Result<FsEvent, AnyError> {
+    loop {
+      let notified = self.notify.notified();
+      let delay = {
+        let mut pending = self.pending.lock();
+        let quiet_for = pending.last_event.map(|last| last.elapsed());
+        match self.debounce.zip(quiet_for) {
+          Some((debounce, quiet_for))
+            if quiet_for < debounce && !pending.events.is_empty() =>
+          {
+            Some(debounce - quiet_for)
+          }
+          _ => {
+            if let Some(event) = pending.events.pop_front() {
+              pending.overflowed = false;
+              return event;
+            }
+            None
+          }
+        }
+      };
+      match delay {
+        Some(delay) => tokio::time::sleep(delay).await,
+        None => notified.await,
+      }
+    }
+  }
+}
+
+impl From<NotifyEvent> for FsEvent {
+  fn from(e: NotifyEvent) -> Self {
+    let mut detail = None;
+    let mut rename = None;
+    let mut file_type = None;
+    let kind = match e.kind {
+      EventKind::Any => "any",
+      EventKind::Access(access) => {
+        detail = match access {
+          AccessKind::Any => None,
+          AccessKind::Read => Some("read"),
+          AccessKind::Open(_) => Some("open"),
+          AccessKind::Close(_) => Some("close"),
+          AccessKind::Other => Some("other"),
+        };
+        "access"
+      }
+      EventKind::Create(create) => {
+        file_type = match create {
+          CreateKind::File => Some("file"),
+          CreateKind::Folder => Some("directory"),
+          CreateKind::Any | CreateKind::Other => None,
+        };
+        "create"
+      }
+      EventKind::Modify(modify) => {
+        detail = match modify {
+          ModifyKind::Any => None,
+          ModifyKind::Data(_) => Some("data"),
+          ModifyKind::Metadata(_) => Some("metadata"),
+          ModifyKind::Name(mode) => {
+            rename = Some(match mode {
+              RenameMode::From => "from",
+              RenameMode::To => "to",
+              RenameMode::Both => "both",
+              RenameMode::Any | RenameMode::Other => "any",
+            });
+            Some("name")
+          }
+          ModifyKind::Other => Some("other"),
+        };
+        "modify"
+      }
+      EventKind::Remove(remove) => {
+        file_type = match remove {
+          RemoveKind::File => Some("file"),
+          RemoveKind::Folder => Some("directory"),
+          RemoveKind::Any | RemoveKind::Other => None,
+        };
+        "remove"
+      }
+      EventKind::Other => "other",
+    };
+    let flag = e.flag().map(|f| match f {
+      notify::event::Flag::Rescan => "rescan",
+    });
+    // Removed paths and the old path of a rename no longer exist.
+    if file_type.is_none() && kind != "remove" && rename != Some("from") {
+      file_type = e.paths.last().and_then(|path| stat_file_type(path));
+    }
+    FsEvent {
+      kind,
+      flag,
+      detail,
+      rename,
+      file_type,
+      tracker: e.attrs.tracker(),
+      paths: e.paths,
+    }
+  }
+}
+
+fn stat_file_type(path: &Path) -> Option<&'static str> {
+  let file_type = std::fs::symlink_metadata(path).ok()?.file_type();
+  if file_type.is_symlink() {
+    Some("symlink")
+  } else if file_type.is_dir() {
+    Some("directory")
+  } else if file_type.is_file() {
+    Some("file")
+  } else {
+    None
+  }
+}
+
+/// The `include` and `exclude` globs of `Deno.watchFs()`.
+///
+/// A pattern matches a path if it matches the path or one of its parent
+/// directories, either as an absolute path or relative to the watched path
+/// it is under. So `node_modules` excludes every `node_modules` directory
+/// directly inside a watched path, and `**/node_modules` every one below it.
+struct PathFilter {
+  roots: Vec<PathBuf>,
+  include: Vec<Pattern>,
+  exclude: Vec<Pattern>,
+}
+
+impl PathFilter {
+  fn new(
+    roots: &[PathBuf],
+    include: &[String],
+    exclude: &[String],
+  ) -> Result<Self, AnyError> {
+    let patterns = |globs: &[String]| {
+      globs
+        .iter()
+        .map(|glob| {
+          Pattern::new(glob).map_err(|err| {
+            type_error(format!("Invalid glob pattern \"{glob}\": {err}"))
+          })
+        })
+        .collect::<Result<Vec<_>, _>>()
+    };
+    // Events may be reported for the canonical path rather than the one
+    // given, e.g. on macOS.
+    let roots = roots
+      .iter()
+      .flat_map(|root| [root.clone(), root.canonicalize().unwrap_or_default()])
+      .filter(|root| !root.as_os_str().is_empty())
+      .collect();
+    Ok(Self {
+      roots,
+      include: patterns(include)?,
+      exclude: patterns(exclude)?,
+    })
+  }
+
+  fn is_empty(&self) -> bool {
+    self.include.is_empty() && self.exclude.is_empty()
+  }
+
+  fn accepts(&self, path: &Path) -> bool {
+    (self.include.is_empty() || self.matches(&self.include, path))
+      && !self.excludes(path)
+  }
+
+  fn excludes(&self, path: &Path) -> bool {
+    self.matches(&self.exclude, path)
+  }
+
+  fn matches(&self, patterns: &[Pattern], path: &Path) -> bool {
+    path.ancestors().any(|ancestor| {
+      let relative = self
+        .roots
+        .iter()
+        .filter_map(|root| ancestor.strip_prefix(root).ok())
+        .filter(|relative| !relative.as_os_str().is_empty());
+      std::iter::once(ancestor)
+        .chain(relative)
+        .any(|path| patterns.iter().any(|pattern| pattern.matches_path(path)))
+    })
+  }
+
+  /// Events without paths, like rescans, always pass.
+  fn accepts_event(&self, event: &NotifyEvent) -> bool {
+    event.paths.is_empty() || event.paths.iter().any(|path| self.accepts(path))
+  }
+}
+
+/// Watches `dir` and, recursively, every directory below it that is not
+/// excluded, each without `RecursiveMode::Recursive`, so that excluded
+/// directories are never descended into. Directories that cannot be read
+/// are skipped, like the watcher does when it descends itself.
+fn watch_descending(
+  watcher: &mut dyn Watcher,
+  dir: &Path,
+  filter: &PathFilter,
+) -> Result<(), NotifyError> {
+  watcher.watch(dir, RecursiveMode::NonRecursive)?;
+  let Ok(entries) = std::fs::read_dir(dir) else {
+    return Ok(());
+  };
+  for entry in entries.flatten() {
+    let path = entry.path();
+    let is_dir = entry.file_type().is_ok_and(|file_type| file_type.is_dir());
+    if is_dir && !filter.excludes(&path) {
+      watch_descending(watcher, &path, filter)?;
+    }
+  }
+  Ok(())
+}
+
+/// Watches every path in `paths`, descending into directories itself when
+/// `descend` is given.
+fn watch_paths(
+  watcher: &mut dyn Watcher,
+  paths: &[PathBuf],
+  recursive_mode: RecursiveMode,
+  descend: Option<&PathFilter>,
+) -> Result<(), NotifyError> {
+  for path in paths {
+    match descend {
+      Some(filter) => watch_descending(watcher, path, filter)?,
+      None => watcher.watch(path, recursive_mode)?,
+    }
+  }
+  Ok(())
+}
+
+/// Filters events and queues them, and with `descend` reports directories
+/// that were added and are not excluded, so that they can be watched too.
+fn event_handler(
+  queue: &Arc<EventQueue>,
+  filter: &Arc<PathFilter>,
+  descend: Option<std_mpsc::Sender<PathBuf>>,
+) -> impl Fn(Result<NotifyEvent, NotifyError>) + Send + 'static {
+  let queue = queue.clone();
+  let filter = filter.clone();
+  move |res: Result<NotifyEvent, NotifyError>| {
+    if let Ok(event) = &res {
+      let added = matches!(
+        event.kind,
+        EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(_))
+      );
+      if let (Some(descend), true) = (&descend, added) {
+        for path in &event.paths {
+          if path.is_dir() && !filter.excludes(path) {
+            let _ = descend.send(path.clone());
+          }
+        }
+      }
+      if !filter.is_empty() && !filter.accepts_event(event) {
+        return;
+      }
+    }
+    queue.push(res.map(FsEvent::from).map_err(AnyError::from));
+  }
+}
+
+/// Creates the `PollWatcher` used with `usePolling`, or instead of the OS's
+/// notifications once they run out of watches.
+struct Polling {
+  paths: Vec<PathBuf>,
+  recursive_mode: RecursiveMode,
+  interval: Duration,
+  queue: Arc<EventQueue>,
+  filter: Arc<PathFilter>,
+}
+
+impl Polling {
+  fn watcher(&self) -> Result<Box<dyn Watcher + Send>, NotifyError> {
+    let handler = event_handler(&self.queue, &self.filter, None);
+    let config = Config::default().with_poll_interval(self.interval);
+    let mut watcher: Box<dyn Watcher + Send> =
+      Box::new(PollWatcher::new(handler, config)?);
+    watch_paths(watcher.as_mut(), &self.paths, self.recursive_mode, None)?;
+    Ok(watcher)
+  }
+
+  /// Like `watcher`, announcing the switch with a `pollFallback` event.
+  fn fallback(&self) -> Result<Box<dyn Watcher + Send>, NotifyError> {
+    let watcher = self.watcher()?;
+    self.queue.push(Ok(FsEvent::poll_fallback()));
+    Ok(watcher)
+  }
+}
+
+/// inotify reports running out of watches (ENOSPC) this way.
+fn is_watch_limit(err: &NotifyError) -> bool {
+  matches!(err.kind, NotifyErrorKind::MaxFilesWatch)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpenArgs {
+  recursive: bool,
+  paths: Vec<String>,
+  /// Milliseconds without events to wait for before delivering them.
+  debounce: Option<u64>,
+  queue_capacity: Option<usize>,
+  #[serde(default)]
+  include: Vec<String>,
+  #[serde(default)]
+  exclude: Vec<String>,
+  /// Use a `PollWatcher` instead of the OS's notifications, which are not
+  /// delivered for network file systems and some container mounts.
+  #[serde(default)]
+  use_polling: bool,
+  /// Milliseconds between scans of a `PollWatcher`.
+  poll_interval: Option<u64>,
+}
+
+#[op2]
+#[smi]
+fn op_host_fs_events_open(
+  state: &mut OpState,
+  #[serde] args: OpenArgs,
+) -> Result<ResourceId, AnyError> {
+  let paths = args.paths.iter().map(PathBuf::from).collect::<Vec<_>>();
+  for path in &paths {
+    state
+      .borrow_mut::<PermissionsContainer>()
+      .check_read(path, "Deno.watchFs()")?;
+  }
+  let filter = Arc::new(PathFilter::new(&paths, &args.include, &args.exclude)?);
+  // inotify watches every directory separately, so excluded directories can
+  // be skipped. Other backends watch the whole tree natively and rely on the
+  // events being filtered.
+  let descend = cfg!(target_os = "linux")
+    && !args.use_polling
+    && args.recursive
+    && !filter.exclude.is_empty();
+  let queue = Arc::new(EventQueue::new(
+    args.debounce.map(Duration::from_millis),
+    args.queue_capacity.unwrap_or(DEFAULT_QUEUE_CAPACITY),
+  ));
+  let recursive_mode = if args.recursive {
+    RecursiveMode::Recursive
+  } else {
+    RecursiveMode::NonRecursive
+  };
+  let polling = Polling {
+    paths,
+    recursive_mode,
+    interval: args
+      .poll_interval
+      .map(Duration::from_millis)
+      .unwrap_or(DEFAULT_POLL_INTERVAL),
+    queue: queue.clone(),
+    filter: filter.clone(),
+  };
+
+  let (descend_sender, descend_receiver) = std_mpsc::channel::<PathBuf>();
+  let mut descend_receiver = descend.then_some(descend_receiver);
+  let watcher = if args.use_polling {
+    polling.watcher()?
+  } else {
+    let handler =
+      event_handler(&queue, &filter, descend.then_some(descend_sender));
+    let mut watcher: Box<dyn Watcher + Send> =
+      Box::new(RecommendedWatcher::new(handler, Config::default())?);
+    let descend_filter = descend.then_some(filter.as_ref());
+    match watch_paths(
+      watcher.as_mut(),
+      &polling.paths,
+      recursive_mode,
+      descend_filter,
+    ) {
+      Ok(()) => watcher,
+      Err(err) if is_watch_limit(&err) => {
+        drop(watcher);
+        descend_receiver = None;
+        polling.fallback()?
+      }
+      Err(err) => return Err(err.into()),
+    }
+  };
+  let watcher = Arc::new(Mutex::new(watcher));
+
+  if let Some(descend_receiver) = descend_receiver {
+    // Watches directories created later. The watcher can't be used from its
+    // own event handler, so this happens on a separate thread, which ends
+    // once the watcher and with it the sender are dropped. Running out of
+    // watches here switches to polling as well.
+    let watcher = Arc::downgrade(&watcher);
+    std::thread::spawn(move || {
+      while let Ok(dir) = descend_receiver.recv() {
+        let Some(watcher) = watcher.upgrade() else {
+          break;
+        };
+        let mut watcher = watcher.lock();
+        match watch_descending(watcher.as_mut(), &dir, &polling.filter) {
+          Err(err) if is_watch_limit(&err) => {
+            match polling.fallback() {
+              Ok(poll_watcher) => *watcher = poll_watcher,
+              Err(err) => polling.queue.push(Err(err.into())),
+            }
+            break;
+          }
+          _ => {}
+        }
+      }
+    });
+  }
+  let resource = FsEventsResource {
+    watcher,
+    queue,
+    cancel: Default::default(),
+  };
+  let rid = state.resource_table.add(resource);
+  Ok(rid)
+}
+
+#[op2(async)]
+#[serde]
+async fn op_host_fs_events_poll(
+  state: Rc<RefCell<OpState>>,
+  #[smi] rid: ResourceId,
+) -> Result<Option<FsEvent>, AnyError> {
+  let resource = state.borrow().resource_table.get::<FsEventsResource>(rid)?;
+  let queue = resource.queue.clone();
+  let cancel = RcRef::map(resource, |r| &r.cancel);
+  let event = queue.next().or_cancel(cancel).await??;
+  Ok(Some(event))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use deno_core::serde_json;
+  use deno_core::serde_json::json;
+  use deno_core::JsRuntime;
+
+  fn event(kind: &'static str, path: &str) -> FsEvent {
+    FsEvent {
+      kind,
+      paths: vec![PathBuf::from(path)],
+      flag: None,
+      detail: None,
+      rename: None,
+      file_type: None,
+      tracker: None,
+    }
+  }
+
+  fn modify(path: &str, detail: &'static str) -> FsEvent {
+    FsEvent {
+      detail: Some(detail),
+      ..event("modify", path)
+    }
+  }
+
+  fn rename(half: &'static str, path: &str, tracker: usize) -> FsEvent {
+    FsEvent {
+      rename: Some(half),
+      tracker: Some(tracker),
+      ..modify(path, "name")
+    }
+  }
+
+  /// Kind, detail, paths and flag of every pending event.
+  fn pending(
+    queue: &EventQueue,
+  ) -> Vec<(
+    &'static str,
+    Option<&'static str>,
+    Vec<PathBuf>,
+    Option<&str>,
+  )> {
+    let pending = queue.pending.lock();
+    pending
+      .events
+      .iter()
+      .map(|event| {
+        let event = event.as_ref().unwrap();
+        (event.kind, event.detail, event.paths.clone(), event.flag)
+      })
+      .collect()
+  }
+
+  #[test]
+  fn queue_coalesces_events_for_the_same_path() {
+    let queue = EventQueue::new(None, 16);
+    queue.push(Ok(event("create", "/w/a")));
+    queue.push(Ok(modify("/w/a", "data")));
+    queue.push(Ok(modify("/w/b", "data")));
+    queue.push(Ok(modify("/w/b", "metadata")));
+    queue.push(Ok(event("access", "/w/b")));
+    assert_eq!(
+      pending(&queue),
+      [
+        ("create", None, vec![PathBuf::from("/w/a")], None),
+        ("modify", Some("other"), vec![PathBuf::from("/w/b")], None),
+      ]
+    );
+  }
+
+  #[test]
+  fn queue_overflow_is_replaced_by_a_rescan() {
+    let queue = EventQueue::new(None, 2);
+    queue.push(Ok(event("create", "/w/a")));
+    queue.push(Ok(event("create", "/w/b")));
+    queue.push(Ok(event("create", "/w/c")));
+    queue.push(Ok(event("create", "/w/d")));
+    assert_eq!(pending(&queue), [("other", None, vec![], Some("rescan"))]);
+  }
+
+  #[test]
+  fn rename_halves_are_paired_by_tracker() {
+    let mut from = rename("from", "/w/old", 1);
+    assert!(from.pair_rename(rename("to", "/w/other", 2)).is_err());
+    assert!(from.pair_rename(rename("to", "/w/new", 1)).is_ok());
+    assert_eq!(from.rename, Some("both"));
+    assert_eq!(
+      from.paths,
+      [PathBuf::from("/w/old"), PathBuf::from("/w/new")]
+    );
+  }
+
+  #[test]
+  fn queue_drops_a_rename_it_already_paired() {
+    let queue = EventQueue::new(None, 16);
+    queue.push(Ok(rename("from", "/w/old", 1)));
+    queue.push(Ok(rename("to", "/w/new", 1)));
+    queue.push(Ok(FsEvent {
+      rename: Some("both"),
+      paths: vec![PathBuf::from("/w/old"), PathBuf::from("/w/new")],
+      tracker: None,
+      ..modify("/w/old", "name")
+    }));
+    let paths = vec![PathBuf::from("/w/old"), PathBuf::from("/w/new")];
+    assert_eq!(pending(&queue), [("modify", Some("name"), paths, None)]);
+  }
+
+  #[test]
+  fn path_filter_matches_relative_to_watched_paths() {
+    let filter = PathFilter::new(
+      &[PathBuf::from("/w")],
+      &["src/**".to_string()],
+      &["**/node_modules".to_string()],
+    )
+    .unwrap();
+    assert!(filter.accepts(Path::new("/w/src/a.ts")));
+    assert!(!filter.accepts(Path::new("/w/lib/a.ts")));
+    assert!(!filter.accepts(Path::new("/w/src/node_modules/x/a.ts")));
+    assert!(filter.excludes(Path::new("/w/node_modules")));
+
+    let rescan = NotifyEvent::new(EventKind::Other);
+    assert!(filter.accepts_event(&rescan));
+  }
+
+  #[test]
+  fn path_filter_rejects_invalid_globs() {
+    assert!(PathFilter::new(&[], &["[".to_string()], &[]).is_err());
+  }
+
+  /// Records the directories it is asked to watch.
+  #[derive(Default)]
+  struct RecordingWatcher(Vec<PathBuf>);
+
+  impl Watcher for RecordingWatcher {
+    fn new<F: notify::EventHandler>(
+      _event_handler: F,
+      _config: Config,
+    ) -> Result<Self, NotifyError> {
+      Ok(Self::default())
+    }
+
+    fn watch(
+      &mut self,
+      path: &Path,
+      recursive_mode: RecursiveMode,
+    ) -> Result<(), NotifyError> {
+      assert_eq!(recursive_mode, RecursiveMode::NonRecursive);
+      self.0.push(path.to_path_buf());
+      Ok(())
+    }
+
+    fn unwatch(&mut self, _path: &Path) -> Result<(), NotifyError> {
+      Ok(())
+    }
+
+    fn kind() -> notify::WatcherKind {
+      notify::WatcherKind::NullWatcher
+    }
+  }
+
+  #[test]
+  fn watch_descending_never_watches_excluded_directories() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    for path in ["src/lib", "node_modules/a", "src/node_modules/b"] {
+      std::fs::create_dir_all(root.join(path)).unwrap();
+    }
+    std::fs::write(root.join("src/main.ts"), "").unwrap();
+    let filter = PathFilter::new(
+      &[root.to_path_buf()],
+      &[],
+      &["**/node_modules".to_string()],
+    )
+    .unwrap();
+
+    let mut watcher = RecordingWatcher::default();
+    watch_descending(&mut watcher, root, &filter).unwrap();
+    let mut watched = watcher.0;
+    watched.sort();
+    assert_eq!(
+      watched,
+      [root.to_path_buf(), root.join("src"), root.join("src/lib")]
+    );
+  }
+
+  fn runtime_allowing_reads() -> JsRuntime {
+    let mut runtime = JsRuntime::new(Default::default());
+    runtime
+      .op_state()
+      .borrow_mut()
+      .put(PermissionsContainer::allow_all());
+    runtime
+  }
+
+  /// Polls `rid` until an event that `matches` arrives.
+  async fn poll_until(
+    state: &Rc<RefCell<OpState>>,
+    rid: ResourceId,
+    matches: impl Fn(&FsEvent) -> bool,
+  ) -> FsEvent {
+    let poll = async {
+      loop {
+        let event = op_host_fs_events_poll::call(state.clone(), rid)
+          .await
+          .unwrap()
+          .unwrap();
+        if matches(&event) {
+          return event;
+        }
+      }
+    };
+    tokio::time::timeout(Duration::from_secs(10), poll)
+      .await
+      .unwrap()
+  }
+
+  #[tokio::test]
+  async fn use_polling_delivers_events_to_poll() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut runtime = runtime_allowing_reads();
+    let state = runtime.op_state();
+    let args = serde_json::from_value(json!({
+      "recursive": true,
+      "paths": [dir.path()],
+      "usePolling": true,
+      "pollInterval": 20,
+    }))
+    .unwrap();
+    let rid =
+      op_host_fs_events_open::call(&mut state.borrow_mut(), args).unwrap();
+
+    let file = dir.path().join("a.txt");
+    std::fs::write(&file, "a").unwrap();
+    let event =
+      poll_until(&state, rid, |event| event.paths.contains(&file)).await;
+    assert_eq!(event.kind, "create");
+    assert_eq!(event.flag, None);
+  }
+
+  #[tokio::test]
+  async fn fallback_to_polling_is_flagged_before_its_events() {
+    assert!(is_watch_limit(&NotifyError::new(
+      NotifyErrorKind::MaxFilesWatch
+    )));
+    let dir = tempfile::tempdir().unwrap();
+    let queue = Arc::new(EventQueue::new(None, DEFAULT_QUEUE_CAPACITY));
+    let polling = Polling {
+      paths: vec![dir.path().to_path_buf()],
+      recursive_mode: RecursiveMode::Recursive,
+      interval: Duration::from_millis(20),
+      queue: queue.clone(),
+      filter: Arc::new(PathFilter::new(&[], &[], &[]).unwrap()),
+    };
+    let watcher = polling.fallback().unwrap();
+    let mut runtime = runtime_allowing_reads();
+    let state = runtime.op_state();
+    let rid = state.borrow_mut().resource_table.add(FsEventsResource {
+      watcher: Arc::new(Mutex::new(watcher)),
+      queue,
+      cancel: Default::default(),
+    });
+
+    let file = dir.path().join("a.txt");
+    std::fs::write(&file, "a").unwrap();
+    let event = poll_until(&state, rid, |_| true).await;
+    assert_eq!(event.flag, Some("pollFallback"));
+    let event =
+      poll_until(&state, rid, |event| event.paths.contains(&file)).await;
+    assert_eq!(event.kind, "create");
+  }
+}
@@ -0,0 +1,256 @@
+//! Extensions defined by the embedder rather than by Deno.
+//!
+//! Each one is declared once, as a `HostExtension`, and turned into a
+//! `deno_core::Extension` in one of two ways:
+//!
+//! - `for_snapshot` when creating the snapshot, with its ESM, so the JS is
+//!   evaluated and its namespace installed on `globalThis` ahead of time.
+//! - `for_runtime` when creating a runtime from that snapshot, with only its
+//!   ops. Host state is supplied at this point through `HostState`.
+//!
+//! To add an extension, declare it in a module like `mach` and append it to
+//! `host_extensions()`. It is then part of the canonical list in
+//! `extensions`, which both the snapshot and the runtime are built from.
+
+use std::borrow::Cow;
+use std::sync::Arc;
+
+use deno_core::Extension;
+use deno_core::ExtensionFileSource;
+use deno_core::OpDecl;
+use deno_core::OpState;
+
+pub mod buffers;
+pub mod clock;
+pub mod extensions;
+pub mod fs_events;
+pub mod mach;
+pub mod process;
+pub mod profile;
+pub mod pty;
+
+/// The global the embedder's JS APIs are exposed under, unless
+/// `ExtensionOptions::namespace` names another one.
+pub const DEFAULT_NAMESPACE: &str = "Mach";
+
+/// Every embedder extension, in registration order, with their JS exposed
+/// under `namespace`.
+pub fn host_extensions(namespace: &'static str) -> Vec<HostExtension> {
+  vec![
+    mach::extension(namespace),
+    buffers::extension(namespace),
+    profile::extension(),
+    clock::extension(),
+    process::extension(),
+  ]
+}
+
+/// Extensions without ops whose middleware replaces the implementation of
+/// ops `deno_runtime` registers, keeping their names so that the JS calling
+/// them is unchanged.
+pub fn op_overrides() -> Vec<Extension> {
+  vec![
+    fs_events::host_fs_events::init_ops(),
+    process::host_process::init_ops(),
+  ]
+}
+
+/// `host_extensions()` prepared for a `MainWorker` or `WebWorker` created
+/// from the snapshot, preceded by an extension that puts `state` into the
+/// `OpState` and by `op_overrides()`. A plain `JsRuntime` gets them from
+/// `extensions::extensions`.
+pub fn runtime_extensions(state: &HostState) -> Vec<Extension> {
+  // The namespace only affects the JS, which is already in the snapshot.
+  std::iter::once(state.extension())
+    .chain(op_overrides())
+    .chain(
+      host_extensions(DEFAULT_NAMESPACE)
+        .into_iter()
+        .map(HostExtension::for_runtime),
+    )
+    .collect()
+}
+
+/// Declaration of an embedder extension: its ops and, optionally, the ESM
+/// that wraps them.
+pub struct HostExtension {
+  name: &'static str,
+  ops: Vec<OpDecl>,
+  esm_files: Vec<ExtensionFileSource>,
+  esm_entry_point: Option<&'static str>,
+  namespace: Option<&'static str>,
+}
+
+impl HostExtension {
+  pub fn new(name: &'static str) -> Self {
+    Self {
+      name,
+      ops: vec![],
+      esm_files: vec![],
+      esm_entry_point: None,
+      namespace: None,
+    }
+  }
+
+  pub fn ops(mut self, ops: impl IntoIterator<Item = OpDecl>) -> Self {
+    self.ops.extend(ops);
+    self
+  }
+
+  /// ESM files of the extension, usually from `include_js_files!`.
+  /// `entry_point` is the module whose exports make up the namespace.
+  pub fn esm(
+    mut self,
+    files: impl IntoIterator<Item = ExtensionFileSource>,
+    entry_point: &'static str,
+  ) -> Self {
+    self.esm_files.extend(files);
+    self.esm_entry_point = Some(entry_point);
+    self
+  }
+
+  /// Exposes the exports of the ESM entry point as a frozen object on
+  /// `globalThis[namespace]`. Extensions sharing a namespace are merged.
+  ///
+  /// The namespaces of all host extensions are installed by one generated
+  /// module, see `namespaces_module`.
+  pub fn namespace(mut self, namespace: &'static str) -> Self {
+    self.namespace = Some(namespace);
+    self
+  }
+
+  pub fn name(&self) -> &'static str {
+    self.name
+  }
+
+  /// The extension with its ops and ESM, for `create_snapshot`.
+  pub fn for_snapshot(self) -> Extension {
+    Extension {
+      name: self.name,
+      ops: Cow::Owned(self.ops),
+      esm_files: Cow::Owned(self.esm_files),
+      esm_entry_point: self.esm_entry_point,
+      ..Default::default()
+    }
+  }
+
+  /// The extension with only its ops, for a runtime started from a snapshot
+  /// that already contains its JS.
+  pub fn for_runtime(self) -> Extension {
+    Extension {
+      name: self.name,
+      ops: Cow::Owned(self.ops),
+      ..Default::default()
+    }
+  }
+}
+
+/// A module that imports the ESM entry point of every extension with a
+/// namespace and installs its exports there.
+///
+/// Generating a single module for all of them lets its specifier be a
+/// constant, which `ExtensionFileSource` requires to be `'static`.
+fn namespaces_module(extensions: &[HostExtension]) -> String {
+  let mut code = String::new();
+  let mut namespaces: Vec<(&str, Vec<String>)> = vec![];
+  for (i, extension) in extensions.iter().enumerate() {
+    let (Some(namespace), Some(entry_point)) =
+      (extension.namespace, extension.esm_entry_point)
+    else {
+      continue;
+    };
+    code.push_str(&format!("import * as ext{i} from \"{entry_point}\";\n"));
+    let spread = format!("...ext{i}");
+    match namespaces.iter_mut().find(|(name, _)| *name == namespace) {
+      Some((_, spreads)) => spreads.push(spread),
+      None => namespaces.push((namespace, vec![spread])),
+    }
+  }
+  for (namespace, spreads) in namespaces {
+    code.push_str(&format!(
+      r#"Object.defineProperty(globalThis, "{namespace}", {{
+  value: Object.freeze({{ ...globalThis["{namespace}"], {} }}),
+  enumerable: false,
+  configurable: true,
+  writable: false,
+}});
+"#,
+      spreads.join(", ")
+    ));
+  }
+  code
+}
+
+type PutState = Arc<dyn Fn(&mut OpState) + Send + Sync>;
+
+/// Typed host values to make available to embedder ops through `OpState`.
+///
+/// ```ignore
+/// let state = HostState::default()
+///   .put(MachHost { version: "0.1.0".to_string() });
+/// ```
+///
+/// Every value is cloned into each runtime the state is used for, so the
+/// same `HostState` can configure a whole pool of isolates.
+#[derive(Clone, Default)]
+pub struct HostState(Vec<PutState>);
+
+impl HostState {
+  pub fn put<T>(mut self, value: T) -> Self
+  where
+    T: Clone + Send + Sync + 'static,
+  {
+    self.0.push(Arc::new(move |state| state.put(value.clone())));
+    self
+  }
+
+  /// Like `put`, for values that have to be created on the isolate thread,
+  /// such as `Rc`s.
+  pub fn put_with<T, F>(mut self, init: F) -> Self
+  where
+    T: 'static,
+    F: Fn() -> T + Send + Sync + 'static,
+  {
+    self.0.push(Arc::new(move |state| state.put(init())));
+    self
+  }
+
+  /// An extension without ops that puts the values into the `OpState`.
+  pub fn extension(&self) -> Extension {
+    let puts = self.0.clone();
+    Extension {
+      name: "host_state",
+      op_state_fn: Some(Box::new(move |state: &mut OpState| {
+        for put in &puts {
+          put(state);
+        }
+      })),
+      ..Default::default()
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn namespaces_module_merges_shared_namespaces() {
+    let code = namespaces_module(&[
+      HostExtension::new("a")
+        .esm(vec![], "ext:a/a.js")
+        .namespace("Mach"),
+      HostExtension::new("b").ops([]),
+      HostExtension::new("c")
+        .esm(vec![], "ext:c/c.js")
+        .namespace("Mach"),
+      HostExtension::new("d")
+        .esm(vec![], "ext:d/d.js")
+        .namespace("Other"),
+    ]);
+    assert!(code.contains("import * as ext0 from \"ext:a/a.js\";"));
+    assert!(!code.contains("ext1"));
+    assert!(code.contains("globalThis[\"Mach\"], ...ext0, ...ext2 }"));
+    assert!(code.contains("globalThis[\"Other\"], ...ext3 }"));
+  }
+}
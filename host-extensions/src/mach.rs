@@ -0,0 +1,36 @@
+//! Host APIs for bundler plugins, exposed on the host namespace,
+//! `globalThis.Mach` by default.
+//!
+//! Also serves as the template for new embedder extensions: ops here, their
+//! JS wrappers in `js/`, and the declaration in `extension()`.
+
+use deno_core::include_js_files;
+use deno_core::op2;
+use deno_core::OpState;
+
+use crate::HostExtension;
+
+/// Information about the host, put into the `OpState` with `HostState`.
+#[derive(Clone, Debug, Default)]
+pub struct MachHost {
+  pub version: String,
+}
+
+pub fn extension(namespace: &'static str) -> HostExtension {
+  HostExtension::new("mach")
+    .ops([op_mach_version::DECL])
+    .esm(
+      include_js_files!(mach dir "js", "mach.js",),
+      "ext:mach/mach.js",
+    )
+    .namespace(namespace)
+}
+
+#[op2]
+#[string]
+fn op_mach_version(state: &mut OpState) -> String {
+  state
+    .try_borrow::<MachHost>()
+    .map(|host| host.version.clone())
+    .unwrap_or_default()
+}
@@ -0,0 +1,1610 @@
+// Copyright 2018-2024 the Deno authors. All rights reserved. MIT license.
+
+//! `Deno.Command` with resource limits, process groups, kill-on-drop, pty
+//! stdio, merged output and timeouts.
+//!
+//! `deno_runtime` registers its own `op_spawn_*` ops, which `js/40_process.js`
+//! of `three` imports. The `host_process` extension keeps their names and
+//! arguments and replaces their implementation through its middleware. The
+//! ops without an upstream counterpart, `op_spawn_wait_cancelable`,
+//! `op_spawn_kill_group`, `op_spawn_read_line` and `op_pty_resize`, are
+//! registered by the `process` host extension instead.
+
+use crate::pty;
+use crate::HostExtension;
+use deno_core::anyhow::Context;
+use deno_core::error::type_error;
+use deno_core::error::AnyError;
+use deno_core::futures::future::pending;
+use deno_core::op2;
+use deno_core::serde_json;
+use deno_core::AsyncRefCell;
+use deno_core::CancelFuture;
+use deno_core::CancelHandle;
+use deno_core::CancelTryFuture;
+use deno_core::OpState;
+use deno_core::RcRef;
+use deno_core::Resource;
+use deno_core::ResourceId;
+use deno_core::ToJsBuffer;
+use deno_io::fs::FileResource;
+use deno_io::AsyncPipeRead;
+use deno_io::ChildStderrResource;
+use deno_io::ChildStdinResource;
+use deno_io::ChildStdoutResource;
+use deno_io::PipeRead;
+use deno_runtime::ops::signal::signal_int_to_str;
+use deno_runtime::ops::signal::signal_str_to_int;
+use deno_runtime::ops::tty::ConsoleSize;
+use deno_runtime::permissions::PermissionsContainer;
+use serde::Deserialize;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::cell::RefCell;
+use std::io::Read;
+use std::process::ExitStatus;
+use std::rc::Rc;
+use std::time::Duration;
+use std::time::Instant;
+use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncReadExt;
+use tokio::io::BufReader;
+
+#[cfg(windows)]
+use std::os::windows::process::CommandExt;
+
+#[cfg(unix)]
+use std::os::unix::prelude::ExitStatusExt;
+#[cfg(unix)]
+use std::os::unix::process::CommandExt;
+
+#[derive(Copy, Clone, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Stdio {
+  Inherit,
+  Piped,
+  Null,
+  /// The slave side of a pseudo-terminal, see `pty`. Only accepted
+  /// by `Deno.Command`, which attaches it in `create_command`.
+  Pty,
+}
+
+impl Stdio {
+  pub fn as_stdio(&self) -> std::process::Stdio {
+    match &self {
+      Stdio::Inherit => std::process::Stdio::inherit(),
+      Stdio::Piped => std::process::Stdio::piped(),
+      Stdio::Null => std::process::Stdio::null(),
+      Stdio::Pty => unreachable!("pty stdio is attached by create_command"),
+    }
+  }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub enum StdioOrRid {
+  Stdio(Stdio),
+  Rid(ResourceId),
+}
+
+impl<'de> Deserialize<'de> for StdioOrRid {
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+  where
+    D: serde::Deserializer<'de>,
+  {
+    use serde_json::Value;
+    let value = Value::deserialize(deserializer)?;
+    match value {
+      Value::String(val) => match val.as_str() {
+        "inherit" => Ok(StdioOrRid::Stdio(Stdio::Inherit)),
+        "piped" => Ok(StdioOrRid::Stdio(Stdio::Piped)),
+        "null" => Ok(StdioOrRid::Stdio(Stdio::Null)),
+        "pty" => Ok(StdioOrRid::Stdio(Stdio::Pty)),
+        val => Err(serde::de::Error::unknown_variant(
+          val,
+          &["inherit", "piped", "null", "pty"],
+        )),
+      },
+      Value::Number(val) => match val.as_u64() {
+        Some(val) if val <= ResourceId::MAX as u64 => {
+          Ok(StdioOrRid::Rid(val as ResourceId))
+        }
+        _ => Err(serde::de::Error::custom("Expected a positive integer")),
+      },
+      _ => Err(serde::de::Error::custom(
+        r#"Expected a resource id, "inherit", "piped", "null", or "pty""#,
+      )),
+    }
+  }
+}
+
+impl StdioOrRid {
+  pub fn as_stdio(
+    &self,
+    state: &mut OpState,
+  ) -> Result<std::process::Stdio, AnyError> {
+    match &self {
+      StdioOrRid::Stdio(Stdio::Pty) => {
+        Err(type_error("Pty stdio is only supported by Deno.Command."))
+      }
+      StdioOrRid::Stdio(val) => Ok(val.as_stdio()),
+      StdioOrRid::Rid(rid) => {
+        FileResource::with_file(state, *rid, |file| Ok(file.as_stdio()?))
+      }
+    }
+  }
+}
+
+deno_core::extension!(
+  host_process,
+  middleware = |op| match op.name {
+    "op_spawn_child" => {
+      op.with_implementation_from(&op_host_spawn_child::DECL)
+    }
+    "op_spawn_wait" => {
+      op.with_implementation_from(&op_host_spawn_wait::DECL)
+    }
+    "op_spawn_sync" => {
+      op.with_implementation_from(&op_host_spawn_sync::DECL)
+    }
+    "op_spawn_kill" => {
+      op.with_implementation_from(&op_host_spawn_kill::DECL)
+    }
+    // Disabled rather than left out, as the snapshot and the imports of
+    // `ext:core/ops` expect every op of `deno_process`.
+    #[cfg(not(feature = "deprecated_run"))]
+    "op_run" | "op_run_status" => op.disable(),
+    _ => op,
+  },
+);
+
+pub fn extension() -> HostExtension {
+  HostExtension::new("process").ops([
+    op_spawn_wait_cancelable::DECL,
+    op_spawn_kill_group::DECL,
+    op_spawn_read_line::DECL,
+    pty::op_pty_resize::DECL,
+  ])
+}
+
+/// A spawned child. On Linux it is a `std` child, reaped by `wait_exit`
+/// itself with `wait4` to get its resource usage, rather than by tokio.
+#[cfg(target_os = "linux")]
+type SpawnedChild = std::process::Child;
+#[cfg(not(target_os = "linux"))]
+type SpawnedChild = tokio::process::Child;
+
+struct ChildResource {
+  child: RefCell<SpawnedChild>,
+  /// Stored separately from the RefCell. It's needed for `op_spawn_kill`,
+  /// where the RefCell is borrowed mutably by `op_spawn_wait`.
+  pid: u32,
+  /// Whether the child leads a process group of its own, whose id is `pid`.
+  process_group: bool,
+  kill_on_drop: bool,
+  termination: Termination,
+  spawned_at: Instant,
+  waited: Cell<bool>,
+}
+
+impl ChildResource {
+  /// Sends `signal` to the child, or to its whole process group if it
+  /// leads one.
+  fn terminate(&self, signal: &str) -> Result<(), AnyError> {
+    kill_child(self.pid, self.process_group, signal)
+  }
+}
+
+impl Resource for ChildResource {
+  fn name(&self) -> Cow<str> {
+    "child".into()
+  }
+}
+
+impl Drop for ChildResource {
+  /// Kills a child dropped before it was waited for, e.g. because its runtime
+  /// shut down, together with its process group if it leads one, so that no
+  /// grandchildren are left behind. Not left to tokio's `kill_on_drop`,
+  /// which only signals the child itself.
+  fn drop(&mut self) {
+    if self.kill_on_drop && !self.waited.get() {
+      let _ = self.terminate("SIGKILL");
+    }
+    // Unlike a tokio child, nothing else would reap it.
+    #[cfg(target_os = "linux")]
+    if !self.waited.get() {
+      let pid = self.pid;
+      std::thread::spawn(move || reap(pid, 0, Instant::now()));
+    }
+  }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpawnArgs {
+  cmd: String,
+  args: Vec<String>,
+  cwd: Option<String>,
+  clear_env: bool,
+  env: Vec<(String, String)>,
+  #[cfg(unix)]
+  gid: Option<u32>,
+  #[cfg(unix)]
+  uid: Option<u32>,
+  #[cfg(unix)]
+  #[serde(default)]
+  rlimits: Rlimits,
+  /// Make the child the leader of a new process group.
+  #[cfg(unix)]
+  #[serde(default)]
+  process_group: bool,
+  /// Make the child the leader of a new session, and so of a new process
+  /// group as well, detached from the controlling terminal.
+  #[cfg(unix)]
+  #[serde(default)]
+  setsid: bool,
+  /// Kill the child, and its process group if it leads one, when it is
+  /// dropped without having been waited for.
+  #[serde(default)]
+  kill_on_drop: bool,
+  /// Send stderr to the stdout pipe, so both are read in the order the child
+  /// wrote them.
+  #[serde(default)]
+  merge_output: bool,
+  /// Initial size of the pty if any stdio is `"pty"`.
+  #[serde(default, deserialize_with = "pty::deserialize_size")]
+  pty_size: Option<ConsoleSize>,
+  /// Milliseconds after which the child is terminated, see `Termination`.
+  /// The child then leads a new process group, so that its descendants are
+  /// terminated with it rather than keeping its output pipes open.
+  timeout: Option<u64>,
+  /// Signal terminating the child on timeout. Defaults to `"SIGTERM"`.
+  kill_signal: Option<String>,
+  /// Milliseconds to wait after the child was signaled on timeout or abort
+  /// before sending `SIGKILL`.
+  kill_delay: Option<u64>,
+  #[cfg(windows)]
+  windows_raw_arguments: bool,
+  ipc: Option<i32>,
+
+  #[serde(flatten)]
+  stdio: ChildStdio,
+}
+
+impl SpawnArgs {
+  #[cfg(unix)]
+  fn leads_process_group(&self) -> bool {
+    self.process_group
+      || self.setsid
+      || self.stdio.has_pty()
+      || self.timeout.is_some()
+  }
+
+  #[cfg(not(unix))]
+  fn leads_process_group(&self) -> bool {
+    false
+  }
+
+  fn child_options(&self) -> Result<ChildOptions, AnyError> {
+    let signal = self.kill_signal.as_deref().unwrap_or("SIGTERM");
+    signal_str_to_int(signal)?;
+    Ok(ChildOptions {
+      process_group: self.leads_process_group(),
+      kill_on_drop: self.kill_on_drop,
+      termination: Termination {
+        timeout: self.timeout.map(Duration::from_millis),
+        signal: signal.to_string(),
+        kill_delay: self
+          .kill_delay
+          .map_or(DEFAULT_KILL_DELAY, Duration::from_millis),
+      },
+    })
+  }
+
+  fn merges_output(&self) -> Result<bool, AnyError> {
+    if self.merge_output
+      && (self.stdio.stdout != Stdio::Piped
+        || self.stdio.stderr != Stdio::Piped)
+    {
+      return Err(type_error(
+        "mergeOutput requires stdout and stderr to be \"piped\".",
+      ));
+    }
+    Ok(self.merge_output)
+  }
+}
+
+struct ChildOptions {
+  process_group: bool,
+  kill_on_drop: bool,
+  termination: Termination,
+}
+
+const DEFAULT_KILL_DELAY: Duration = Duration::from_secs(5);
+
+/// How long the sync wait sleeps between checks whether the child exited.
+const SYNC_WAIT_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How a child is stopped once its timeout expired or, for
+/// `op_spawn_wait_cancelable`, its wait was aborted: on timeout it is sent
+/// `signal`, while an aborted child has already been sent `SIGTERM` by
+/// `ChildProcess`. Either way it is sent `SIGKILL` if it still runs after
+/// `kill_delay`.
+struct Termination {
+  timeout: Option<Duration>,
+  signal: String,
+  kill_delay: Duration,
+}
+
+/// Points stdout and stderr of `command` at a single new pipe and returns its
+/// read end. The write ends are closed once `command` is dropped.
+fn merge_output(
+  command: &mut std::process::Command,
+) -> Result<PipeRead, AnyError> {
+  let (read, write) = deno_io::pipe()?;
+  command.stdout(write.try_clone()?);
+  command.stderr(write);
+  Ok(read)
+}
+
+/// The merged stdout and stderr of a child, see `SpawnArgs::merge_output`.
+/// Buffered, so that it can be read both as bytes and line by line.
+struct MergedOutputResource {
+  reader: AsyncRefCell<BufReader<AsyncPipeRead>>,
+  cancel: CancelHandle,
+}
+
+impl MergedOutputResource {
+  fn new(read: PipeRead) -> Result<Self, AnyError> {
+    Ok(Self {
+      reader: AsyncRefCell::new(BufReader::new(read.into_async()?)),
+      cancel: Default::default(),
+    })
+  }
+
+  async fn read(self: Rc<Self>, data: &mut [u8]) -> Result<usize, AnyError> {
+    let mut reader = RcRef::map(&self, |r| &r.reader).borrow_mut().await;
+    let cancel = RcRef::map(&self, |r| &r.cancel);
+    Ok(reader.read(data).try_or_cancel(cancel).await?)
+  }
+
+  /// The next line without its line terminator, or `None` at the end of the
+  /// output. Invalid UTF-8 is replaced.
+  async fn read_line(self: Rc<Self>) -> Result<Option<String>, AnyError> {
+    let mut reader = RcRef::map(&self, |r| &r.reader).borrow_mut().await;
+    let cancel = RcRef::map(&self, |r| &r.cancel);
+    let mut line = Vec::new();
+    let nread = reader
+      .read_until(b'\n', &mut line)
+      .try_or_cancel(cancel)
+      .await?;
+    if nread == 0 {
+      return Ok(None);
+    }
+    if line.ends_with(b"\n") {
+      line.pop();
+      if line.ends_with(b"\r") {
+        line.pop();
+      }
+    }
+    Ok(Some(String::from_utf8_lossy(&line).into_owned()))
+  }
+}
+
+impl Resource for MergedOutputResource {
+  deno_core::impl_readable_byob!();
+
+  fn name(&self) -> Cow<str> {
+    "childMergedOutput".into()
+  }
+
+  fn close(self: Rc<Self>) {
+    self.cancel.cancel();
+  }
+}
+
+/// Resource limits applied to the child with `setrlimit`, as both the soft
+/// and the hard limit.
+#[cfg(unix)]
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Rlimits {
+  /// CPU time in seconds, after which the child receives `SIGXCPU`.
+  cpu: Option<u64>,
+  /// Size of the virtual address space in bytes.
+  address_space: Option<u64>,
+  /// One more than the highest file descriptor the child may open.
+  open_files: Option<u64>,
+}
+
+#[cfg(unix)]
+impl Rlimits {
+  fn is_empty(&self) -> bool {
+    self.cpu.is_none()
+      && self.address_space.is_none()
+      && self.open_files.is_none()
+  }
+
+  /// Runs in the child between `fork` and `exec`, so it must not allocate.
+  fn apply(&self) -> std::io::Result<()> {
+    let limits = [
+      (libc::RLIMIT_CPU, self.cpu),
+      (libc::RLIMIT_AS, self.address_space),
+      (libc::RLIMIT_NOFILE, self.open_files),
+    ];
+    for (resource, limit) in limits {
+      let Some(limit) = limit else {
+        continue;
+      };
+      let rlimit = libc::rlimit {
+        rlim_cur: limit as libc::rlim_t,
+        rlim_max: limit as libc::rlim_t,
+      };
+      // SAFETY: `rlimit` outlives the call.
+      if unsafe { libc::setrlimit(resource, &rlimit) } != 0 {
+        return Err(std::io::Error::last_os_error());
+      }
+    }
+    Ok(())
+  }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChildStdio {
+  stdin: Stdio,
+  stdout: Stdio,
+  stderr: Stdio,
+}
+
+impl ChildStdio {
+  fn has_pty(&self) -> bool {
+    [self.stdin, self.stdout, self.stderr].contains(&Stdio::Pty)
+  }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChildStatus {
+  success: bool,
+  code: i32,
+  signal: Option<String>,
+  /// Whether the child was terminated because its timeout expired.
+  timed_out: bool,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  usage: Option<ChildUsage>,
+}
+
+impl ChildStatus {
+  fn from_exit(exit: Exit) -> Result<Self, AnyError> {
+    let mut status = Self::try_from(exit.status)?;
+    status.usage = exit.usage;
+    Ok(status)
+  }
+}
+
+/// Resource usage of a child, only collected on Linux.
+///
+/// Reported by the `wait4` that reaps the child, so it covers the child and
+/// those of its descendants it waited for, but no other child of the
+/// process.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub struct ChildUsage {
+  /// Milliseconds of CPU time spent in user mode.
+  user_cpu_time: f64,
+  /// Milliseconds of CPU time spent in the kernel.
+  system_cpu_time: f64,
+  /// Maximum resident set size in bytes.
+  max_rss: u64,
+  /// Milliseconds from spawning the child until it was reaped.
+  wall_time: f64,
+}
+
+impl TryFrom<ExitStatus> for ChildStatus {
+  type Error = AnyError;
+
+  fn try_from(status: ExitStatus) -> Result<Self, Self::Error> {
+    let code = status.code();
+    #[cfg(unix)]
+    let signal = status.signal();
+    #[cfg(not(unix))]
+    let signal: Option<i32> = None;
+
+    let status = if let Some(signal) = signal {
+      ChildStatus {
+        success: false,
+        code: 128 + signal,
+        #[cfg(unix)]
+        signal: Some(signal_int_to_str(signal)?.to_string()),
+        #[cfg(not(unix))]
+        signal: None,
+        timed_out: false,
+        usage: None,
+      }
+    } else {
+      let code = code.expect("Should have either an exit code or a signal.");
+
+      ChildStatus {
+        success: code == 0,
+        code,
+        signal: None,
+        timed_out: false,
+        usage: None,
+      }
+    };
+
+    Ok(status)
+  }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpawnOutput {
+  status: ChildStatus,
+  stdout: Option<ToJsBuffer>,
+  stderr: Option<ToJsBuffer>,
+}
+
+type CreateCommand =
+  (std::process::Command, Option<ResourceId>, Option<pty::Pty>);
+
+fn create_command(
+  state: &mut OpState,
+  args: SpawnArgs,
+  api_name: &str,
+) -> Result<CreateCommand, AnyError> {
+  state
+    .borrow_mut::<PermissionsContainer>()
+    .check_run(&args.cmd, api_name)?;
+
+  let mut command = std::process::Command::new(args.cmd);
+
+  #[cfg(windows)]
+  if args.windows_raw_arguments {
+    for arg in args.args.iter() {
+      command.raw_arg(arg);
+    }
+  } else {
+    command.args(args.args);
+  }
+
+  #[cfg(not(windows))]
+  command.args(args.args);
+
+  if let Some(cwd) = args.cwd {
+    command.current_dir(cwd);
+  }
+
+  if args.clear_env {
+    command.env_clear();
+  }
+  command.envs(args.env);
+
+  #[cfg(unix)]
+  if let Some(gid) = args.gid {
+    command.gid(gid);
+  }
+  #[cfg(unix)]
+  if let Some(uid) = args.uid {
+    command.uid(uid);
+  }
+
+  let pty = if args.stdio.has_pty() {
+    Some(pty::Pty::open(args.pty_size.unwrap_or(pty::DEFAULT_SIZE))?)
+  } else {
+    None
+  };
+
+  #[cfg(unix)]
+  if let Some(pty) = &pty {
+    // Also starts a new session, which makes the child lead a new process
+    // group too.
+    pty.set_controlling_terminal(&mut command);
+  } else if args.setsid {
+    // Not combined with `process_group`: `setsid` fails for a process that
+    // already leads a process group.
+    // SAFETY: `setsid` is async-signal-safe.
+    unsafe {
+      command.pre_exec(|| {
+        if libc::setsid() == -1 {
+          return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+      });
+    }
+  } else if args.process_group || args.timeout.is_some() {
+    command.process_group(0);
+  }
+  #[cfg(unix)]
+  if !args.rlimits.is_empty() {
+    let rlimits = args.rlimits;
+    // SAFETY: `setrlimit` is async-signal-safe.
+    unsafe {
+      command.pre_exec(move || rlimits.apply());
+    }
+  }
+
+  let pty_stdio = || pty.as_ref().expect("pty should be open").stdio();
+  command.stdin(match args.stdio.stdin {
+    Stdio::Pty => pty_stdio()?,
+    value => value.as_stdio(),
+  });
+  command.stdout(match args.stdio.stdout {
+    Stdio::Inherit => StdioOrRid::Rid(1).as_stdio(state)?,
+    Stdio::Pty => pty_stdio()?,
+    value => value.as_stdio(),
+  });
+  command.stderr(match args.stdio.stderr {
+    Stdio::Inherit => StdioOrRid::Rid(2).as_stdio(state)?,
+    Stdio::Pty => pty_stdio()?,
+    value => value.as_stdio(),
+  });
+
+  #[cfg(unix)]
+  // TODO(bartlomieju):
+  #[allow(clippy::undocumented_unsafe_blocks)]
+  unsafe {
+    if let Some(ipc) = args.ipc {
+      if ipc < 0 {
+        return Ok((command, None, pty));
+      }
+      // SockFlag is broken on macOS
+      // https://github.com/nix-rust/nix/issues/861
+      let mut fds = [-1, -1];
+      #[cfg(not(target_os = "macos"))]
+      let flags = libc::SOCK_CLOEXEC | libc::SOCK_NONBLOCK;
+
+      #[cfg(target_os = "macos")]
+      let flags = 0;
+
+      let ret = libc::socketpair(
+        libc::AF_UNIX,
+        libc::SOCK_STREAM | flags,
+        0,
+        fds.as_mut_ptr(),
+      );
+      if ret != 0 {
+        return Err(std::io::Error::last_os_error().into());
+      }
+
+      if cfg!(target_os = "macos") {
+        let fcntl =
+          |fd: i32, flag: libc::c_int| -> Result<(), std::io::Error> {
+            let flags = libc::fcntl(fd, libc::F_GETFL, 0);
+
+            if flags == -1 {
+              return Err(fail(fds));
+            }
+            let ret = libc::fcntl(fd, libc::F_SETFL, flags | flag);
+            if ret == -1 {
+              return Err(fail(fds));
+            }
+            Ok(())
+          };
+
+        fn fail(fds: [i32; 2]) -> std::io::Error {
+          unsafe {
+            libc::close(fds[0]);
+            libc::close(fds[1]);
+          }
+          std::io::Error::last_os_error()
+        }
+
+        // SOCK_NONBLOCK is not supported on macOS.
+        (fcntl)(fds[0], libc::O_NONBLOCK)?;
+        (fcntl)(fds[1], libc::O_NONBLOCK)?;
+
+        // SOCK_CLOEXEC is not supported on macOS.
+        (fcntl)(fds[0], libc::FD_CLOEXEC)?;
+        (fcntl)(fds[1], libc::FD_CLOEXEC)?;
+      }
+
+      let fd1 = fds[0];
+      let fd2 = fds[1];
+
+      command.pre_exec(move || {
+        if ipc >= 0 {
+          let _fd = libc::dup2(fd2, ipc);
+          libc::close(fd2);
+        }
+        libc::setgroups(0, std::ptr::null());
+        Ok(())
+      });
+
+      /* One end returned to parent process (this) */
+      let pipe_rid = Some(ipc_resource(state, fd1 as _)?);
+
+      /* The other end passed to child process via DENO_CHANNEL_FD */
+      command.env("DENO_CHANNEL_FD", format!("{}", ipc));
+
+      return Ok((command, pipe_rid, pty));
+    }
+
+    Ok((command, None, pty))
+  }
+
+  #[cfg(windows)]
+  // Safety: We setup a windows named pipe and pass one end to the child process.
+  unsafe {
+    use windows_sys::Win32::Foundation::CloseHandle;
+    use windows_sys::Win32::Foundation::DuplicateHandle;
+    use windows_sys::Win32::Foundation::DUPLICATE_SAME_ACCESS;
+    use windows_sys::Win32::Foundation::ERROR_ACCESS_DENIED;
+    use windows_sys::Win32::Foundation::ERROR_PIPE_CONNECTED;
+    use windows_sys::Win32::Foundation::GENERIC_READ;
+    use windows_sys::Win32::Foundation::GENERIC_WRITE;
+    use windows_sys::Win32::Foundation::INVALID_HANDLE_VALUE;
+    use windows_sys::Win32::Security::SECURITY_ATTRIBUTES;
+    use windows_sys::Win32::Storage::FileSystem::CreateFileW;
+    use windows_sys::Win32::Storage::FileSystem::FILE_FLAG_FIRST_PIPE_INSTANCE;
+    use windows_sys::Win32::Storage::FileSystem::FILE_FLAG_OVERLAPPED;
+    use windows_sys::Win32::Storage::FileSystem::OPEN_EXISTING;
+    use windows_sys::Win32::Storage::FileSystem::PIPE_ACCESS_DUPLEX;
+    use windows_sys::Win32::System::Pipes::ConnectNamedPipe;
+    use windows_sys::Win32::System::Pipes::CreateNamedPipeW;
+    use windows_sys::Win32::System::Pipes::PIPE_READMODE_BYTE;
+    use windows_sys::Win32::System::Pipes::PIPE_TYPE_BYTE;
+    use windows_sys::Win32::System::Threading::GetCurrentProcess;
+
+    use std::io;
+    use std::os::windows::ffi::OsStrExt;
+    use std::path::Path;
+    use std::ptr;
+
+    if let Some(ipc) = args.ipc {
+      if ipc < 0 {
+        return Ok((command, None, pty));
+      }
+
+      let (path, hd1) = loop {
+        let name = format!("\\\\.\\pipe\\{}", uuid::Uuid::new_v4());
+        let mut path = Path::new(&name)
+          .as_os_str()
+          .encode_wide()
+          .collect::<Vec<_>>();
+        path.push(0);
+
+        let hd1 = CreateNamedPipeW(
+          path.as_ptr(),
+          PIPE_ACCESS_DUPLEX
+            | FILE_FLAG_FIRST_PIPE_INSTANCE
+            | FILE_FLAG_OVERLAPPED,
+          PIPE_TYPE_BYTE | PIPE_READMODE_BYTE,
+          1,
+          65536,
+          65536,
+          0,
+          std::ptr::null_mut(),
+        );
+
+        if hd1 == INVALID_HANDLE_VALUE {
+          let err = io::Error::last_os_error();
+          /* If the pipe name is already in use, try again. */
+          if err.raw_os_error() == Some(ERROR_ACCESS_DENIED as i32) {
+            continue;
+          }
+
+          return Err(err.into());
+        }
+
+        break (path, hd1);
+      };
+
+      /* Create child pipe handle. */
+      let s = SECURITY_ATTRIBUTES {
+        nLength: std::mem::size_of::<SECURITY_ATTRIBUTES>() as u32,
+        lpSecurityDescriptor: ptr::null_mut(),
+        bInheritHandle: 1,
+      };
+      let mut hd2 = CreateFileW(
+        path.as_ptr(),
+        GENERIC_READ | GENERIC_WRITE,
+        0,
+        &s,
+        OPEN_EXISTING,
+        FILE_FLAG_OVERLAPPED,
+        0,
+      );
+      if hd2 == INVALID_HANDLE_VALUE {
+        return Err(io::Error::last_os_error().into());
+      }
+
+      // Will not block because we have create the pair.
+      if ConnectNamedPipe(hd1, ptr::null_mut()) == 0 {
+        let err = std::io::Error::last_os_error();
+        if err.raw_os_error() != Some(ERROR_PIPE_CONNECTED as i32) {
+          CloseHandle(hd2);
+          return Err(err.into());
+        }
+      }
+
+      // Duplicating the handle to allow the child process to use it.
+      if DuplicateHandle(
+        GetCurrentProcess(),
+        hd2,
+        GetCurrentProcess(),
+        &mut hd2,
+        0,
+        1,
+        DUPLICATE_SAME_ACCESS,
+      ) == 0
+      {
+        return Err(std::io::Error::last_os_error().into());
+      }
+
+      /* One end returned to parent process (this) */
+      let pipe_fd = Some(ipc_resource(state, hd1 as i64)?);
+
+      /* The other end passed to child process via DENO_CHANNEL_FD */
+      command.env("DENO_CHANNEL_FD", format!("{}", hd2 as i64));
+
+      return Ok((command, pipe_fd, pty));
+    }
+  }
+
+  #[cfg(not(unix))]
+  return Ok((command, None, pty));
+}
+
+/// Adds the parent's end of the IPC channel of `node:child_process`.
+#[cfg(feature = "node")]
+fn ipc_resource(state: &mut OpState, fd: i64) -> Result<ResourceId, AnyError> {
+  let resource = deno_node::IpcJsonStreamResource::new(fd)?;
+  Ok(state.resource_table.add(resource))
+}
+
+#[cfg(not(feature = "node"))]
+fn ipc_resource(
+  _state: &mut OpState,
+  _fd: i64,
+) -> Result<ResourceId, AnyError> {
+  Err(type_error("IPC requires the node feature."))
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Child {
+  rid: ResourceId,
+  pid: u32,
+  stdin_rid: Option<ResourceId>,
+  stdout_rid: Option<ResourceId>,
+  stderr_rid: Option<ResourceId>,
+  pipe_fd: Option<ResourceId>,
+  pty_rid: Option<ResourceId>,
+}
+
+fn spawn_child(
+  state: &mut OpState,
+  command: std::process::Command,
+  pipe_fd: Option<ResourceId>,
+  pty: Option<pty::Pty>,
+  merged_output: Option<PipeRead>,
+  options: ChildOptions,
+) -> Result<Child, AnyError> {
+  #[cfg(not(target_os = "linux"))]
+  let mut command = tokio::process::Command::from(command);
+  #[cfg(target_os = "linux")]
+  let mut command = command;
+
+  let spawned_at = Instant::now();
+  let mut child = match command.spawn() {
+    Ok(child) => child,
+    Err(err) => {
+      #[cfg(not(target_os = "linux"))]
+      let command = command.as_std();
+      let command_name = command.get_program().to_string_lossy();
+
+      if let Some(cwd) = command.get_current_dir() {
+        // launching a sub process always depends on the real
+        // file system so using these methods directly is ok
+        #[allow(clippy::disallowed_methods)]
+        if !cwd.exists() {
+          return Err(
+            std::io::Error::new(
+              std::io::ErrorKind::NotFound,
+              format!(
+                "Failed to spawn '{}': No such cwd '{}'",
+                command_name,
+                cwd.to_string_lossy()
+              ),
+            )
+            .into(),
+          );
+        }
+
+        #[allow(clippy::disallowed_methods)]
+        if !cwd.is_dir() {
+          return Err(
+            std::io::Error::new(
+              std::io::ErrorKind::NotFound,
+              format!(
+                "Failed to spawn '{}': cwd is not a directory '{}'",
+                command_name,
+                cwd.to_string_lossy()
+              ),
+            )
+            .into(),
+          );
+        }
+      }
+
+      return Err(AnyError::from(err).context(format!(
+        "Failed to spawn '{}'",
+        command.get_program().to_string_lossy()
+      )));
+    }
+  };
+
+  #[cfg(target_os = "linux")]
+  let pid = child.id();
+  #[cfg(not(target_os = "linux"))]
+  let pid = child.id().expect("Process ID should be set.");
+
+  #[cfg(target_os = "linux")]
+  let (stdin, stdout, stderr) = (
+    child
+      .stdin
+      .take()
+      .map(tokio::process::ChildStdin::from_std)
+      .transpose()?,
+    child
+      .stdout
+      .take()
+      .map(tokio::process::ChildStdout::from_std)
+      .transpose()?,
+    child
+      .stderr
+      .take()
+      .map(tokio::process::ChildStderr::from_std)
+      .transpose()?,
+  );
+  #[cfg(not(target_os = "linux"))]
+  let (stdin, stdout, stderr) =
+    (child.stdin.take(), child.stdout.take(), child.stderr.take());
+
+  let stdin_rid = stdin
+    .map(|stdin| state.resource_table.add(ChildStdinResource::from(stdin)));
+
+  let stdout_rid = match merged_output {
+    Some(read) => {
+      Some(state.resource_table.add(MergedOutputResource::new(read)?))
+    }
+    None => stdout.map(|stdout| {
+      state.resource_table.add(ChildStdoutResource::from(stdout))
+    }),
+  };
+
+  let stderr_rid = stderr
+    .map(|stderr| state.resource_table.add(ChildStderrResource::from(stderr)));
+
+  let pty_rid = pty.map(|pty| pty.spawned(state)).transpose()?;
+
+  let child_rid = state.resource_table.add(ChildResource {
+    child: RefCell::new(child),
+    pid,
+    process_group: options.process_group,
+    kill_on_drop: options.kill_on_drop,
+    termination: options.termination,
+    spawned_at,
+    waited: Cell::new(false),
+  });
+
+  Ok(Child {
+    rid: child_rid,
+    pid,
+    stdin_rid,
+    stdout_rid,
+    stderr_rid,
+    pipe_fd,
+    pty_rid,
+  })
+}
+
+#[op2]
+#[serde]
+fn op_host_spawn_child(
+  state: &mut OpState,
+  #[serde] args: SpawnArgs,
+  #[string] api_name: String,
+) -> Result<Child, AnyError> {
+  let options = args.child_options()?;
+  let merges_output = args.merges_output()?;
+  let (mut command, pipe_rid, pty) = create_command(state, args, &api_name)?;
+  let merged_output = if merges_output {
+    Some(merge_output(&mut command)?)
+  } else {
+    None
+  };
+  spawn_child(state, command, pipe_rid, pty, merged_output, options)
+}
+
+#[op2(async)]
+#[serde]
+async fn op_host_spawn_wait(
+  state: Rc<RefCell<OpState>>,
+  #[smi] rid: ResourceId,
+) -> Result<ChildStatus, AnyError> {
+  spawn_wait(state, rid, None).await
+}
+
+/// Like `op_spawn_wait`, but also terminates the child once `cancel_rid` is
+/// canceled, which `Deno.Command` does when its abort signal fires.
+#[op2(async)]
+#[serde]
+async fn op_spawn_wait_cancelable(
+  state: Rc<RefCell<OpState>>,
+  #[smi] rid: ResourceId,
+  #[smi] cancel_rid: ResourceId,
+) -> Result<ChildStatus, AnyError> {
+  let cancel_handle = state
+    .borrow_mut()
+    .resource_table
+    .get::<CancelHandle>(cancel_rid)
+    .ok();
+  spawn_wait(state, rid, cancel_handle).await
+}
+
+#[allow(clippy::await_holding_refcell_ref)]
+async fn spawn_wait(
+  state: Rc<RefCell<OpState>>,
+  rid: ResourceId,
+  cancel_handle: Option<Rc<CancelHandle>>,
+) -> Result<ChildStatus, AnyError> {
+  let resource = state
+    .borrow_mut()
+    .resource_table
+    .get::<ChildResource>(rid)?;
+  let result = wait_child(&resource, cancel_handle).await;
+  resource.waited.set(result.is_ok());
+  if let Ok(resource) = state.borrow_mut().resource_table.take_any(rid) {
+    resource.close();
+  }
+  result
+}
+
+/// Waits for the child to exit, terminating it once its timeout expires or
+/// `cancel_handle` is canceled.
+#[allow(clippy::await_holding_refcell_ref)]
+async fn wait_child(
+  resource: &ChildResource,
+  cancel_handle: Option<Rc<CancelHandle>>,
+) -> Result<ChildStatus, AnyError> {
+  let mut child = resource.child.try_borrow_mut()?;
+  let termination = &resource.termination;
+  let expired = async {
+    match termination.timeout {
+      Some(timeout) => tokio::time::sleep(timeout).await,
+      None => pending().await,
+    }
+  };
+  let aborted = async {
+    match cancel_handle {
+      Some(cancel_handle) => {
+        let _ = pending::<()>().or_cancel(cancel_handle).await;
+      }
+      None => pending().await,
+    }
+  };
+  let spawned_at = resource.spawned_at;
+  let timed_out = tokio::select! {
+    exit = wait_exit(&mut child, spawned_at) => {
+      return ChildStatus::from_exit(exit?);
+    }
+    _ = expired => true,
+    _ = aborted => false,
+  };
+
+  // An aborted child was sent `SIGTERM` by `ChildProcess` already. The
+  // child may have exited in the meantime, in which case there is nothing
+  // left to signal.
+  if timed_out {
+    let _ = resource.terminate(&termination.signal);
+  }
+  let exit = match tokio::time::timeout(
+    termination.kill_delay,
+    wait_exit(&mut child, spawned_at),
+  )
+  .await
+  {
+    Ok(exit) => exit?,
+    Err(_) => {
+      let _ = resource.terminate("SIGKILL");
+      wait_exit(&mut child, spawned_at).await?
+    }
+  };
+  let mut status = ChildStatus::from_exit(exit)?;
+  status.timed_out = timed_out;
+  Ok(status)
+}
+
+/// Synchronous counterpart of `wait_child`, without cancellation.
+fn wait_child_sync(
+  child: &mut std::process::Child,
+  spawned_at: Instant,
+  process_group: bool,
+  termination: &Termination,
+) -> Result<ChildStatus, AnyError> {
+  let mut timed_out = false;
+  let exit = match termination.timeout {
+    None => wait_exit_sync(child, spawned_at)?,
+    Some(timeout) => {
+      match wait_until(child, spawned_at, Instant::now() + timeout)? {
+        Some(exit) => exit,
+        None => {
+          timed_out = true;
+          let _ = kill_child(child.id(), process_group, &termination.signal);
+          let deadline = Instant::now() + termination.kill_delay;
+          match wait_until(child, spawned_at, deadline)? {
+            Some(exit) => exit,
+            None => {
+              let _ = kill_child(child.id(), process_group, "SIGKILL");
+              wait_exit_sync(child, spawned_at)?
+            }
+          }
+        }
+      }
+    }
+  };
+  let mut status = ChildStatus::from_exit(exit)?;
+  status.timed_out = timed_out;
+  Ok(status)
+}
+
+/// How the child exited if it exited before `deadline`.
+fn wait_until(
+  child: &mut std::process::Child,
+  spawned_at: Instant,
+  deadline: Instant,
+) -> Result<Option<Exit>, AnyError> {
+  loop {
+    if let Some(exit) = try_wait_exit(child, spawned_at)? {
+      return Ok(Some(exit));
+    }
+    let now = Instant::now();
+    if now >= deadline {
+      return Ok(None);
+    }
+    std::thread::sleep(SYNC_WAIT_INTERVAL.min(deadline - now));
+  }
+}
+
+/// How a child exited, see `wait_exit`.
+struct Exit {
+  status: ExitStatus,
+  usage: Option<ChildUsage>,
+}
+
+/// Waits for the child to exit and reaps it. Every `SIGCHLD` checks whether
+/// it was this child that exited.
+#[cfg(target_os = "linux")]
+async fn wait_exit(
+  child: &mut SpawnedChild,
+  spawned_at: Instant,
+) -> Result<Exit, AnyError> {
+  use tokio::signal::unix::signal;
+  use tokio::signal::unix::SignalKind;
+
+  // Listening before the first attempt, so that the child can't exit
+  // unnoticed in between.
+  let mut sigchld = signal(SignalKind::child())?;
+  loop {
+    if let Some(exit) = try_wait_exit(child, spawned_at)? {
+      return Ok(exit);
+    }
+    if sigchld.recv().await.is_none() {
+      return Err(deno_core::error::generic_error(
+        "No longer notified when children exit",
+      ));
+    }
+  }
+}
+
+/// Waits for the child to exit and lets tokio reap it.
+#[cfg(not(target_os = "linux"))]
+async fn wait_exit(
+  child: &mut SpawnedChild,
+  _spawned_at: Instant,
+) -> Result<Exit, AnyError> {
+  let status = child.wait().await?;
+  Ok(Exit {
+    status,
+    usage: None,
+  })
+}
+
+/// Blocks until the child exits and reaps it.
+fn wait_exit_sync(
+  child: &mut std::process::Child,
+  spawned_at: Instant,
+) -> Result<Exit, AnyError> {
+  #[cfg(target_os = "linux")]
+  {
+    let exit = reap(child.id(), 0, spawned_at)?;
+    Ok(exit.expect("wait4 without WNOHANG should wait for the exit"))
+  }
+  #[cfg(not(target_os = "linux"))]
+  {
+    let _ = spawned_at;
+    let status = child.wait()?;
+    Ok(Exit {
+      status,
+      usage: None,
+    })
+  }
+}
+
+/// Reaps the child if it has exited.
+fn try_wait_exit(
+  child: &mut std::process::Child,
+  spawned_at: Instant,
+) -> Result<Option<Exit>, AnyError> {
+  #[cfg(target_os = "linux")]
+  {
+    Ok(reap(child.id(), libc::WNOHANG, spawned_at)?)
+  }
+  #[cfg(not(target_os = "linux"))]
+  {
+    let _ = spawned_at;
+    let status = child.try_wait()?;
+    Ok(status.map(|status| Exit {
+      status,
+      usage: None,
+    }))
+  }
+}
+
+/// Reaps the child with `pid` through `wait4`, which reports the resource
+/// usage of that child alone. `None` if `options` contain `WNOHANG` and the
+/// child still runs.
+#[cfg(target_os = "linux")]
+fn reap(
+  pid: u32,
+  options: libc::c_int,
+  spawned_at: Instant,
+) -> Result<Option<Exit>, std::io::Error> {
+  let mut status = 0;
+  // SAFETY: all-zero is a valid `rusage`.
+  let mut rusage: libc::rusage = unsafe { std::mem::zeroed() };
+  loop {
+    // SAFETY: `status` and `rusage` outlive the call.
+    let ret = unsafe {
+      libc::wait4(pid as libc::pid_t, &mut status, options, &mut rusage)
+    };
+    match ret {
+      0 => return Ok(None),
+      -1 => {
+        let err = std::io::Error::last_os_error();
+        if err.kind() != std::io::ErrorKind::Interrupted {
+          return Err(err);
+        }
+      }
+      _ => break,
+    }
+  }
+  let millis = |time: libc::timeval| {
+    time.tv_sec as f64 * 1000.0 + time.tv_usec as f64 / 1000.0
+  };
+  Ok(Some(Exit {
+    status: ExitStatus::from_raw(status),
+    usage: Some(ChildUsage {
+      user_cpu_time: millis(rusage.ru_utime),
+      system_cpu_time: millis(rusage.ru_stime),
+      // Reported in kilobytes.
+      max_rss: rusage.ru_maxrss as u64 * 1024,
+      wall_time: spawned_at.elapsed().as_secs_f64() * 1000.0,
+    }),
+  }))
+}
+
+/// Reads `reader` to its end on another thread, so that the child can't
+/// block on a full pipe while it's being waited for.
+fn read_to_end_in_background(
+  mut reader: impl Read + Send + 'static,
+) -> std::thread::JoinHandle<std::io::Result<Vec<u8>>> {
+  std::thread::spawn(move || {
+    let mut buf = Vec::new();
+    reader.read_to_end(&mut buf)?;
+    Ok(buf)
+  })
+}
+
+fn join_output(
+  reader: Option<std::thread::JoinHandle<std::io::Result<Vec<u8>>>>,
+) -> Result<Option<ToJsBuffer>, AnyError> {
+  let Some(reader) = reader else {
+    return Ok(None);
+  };
+  let output = reader
+    .join()
+    .map_err(|_| type_error("Failed to read the child's output."))??;
+  Ok(Some(output.into()))
+}
+
+#[op2]
+#[serde]
+fn op_host_spawn_sync(
+  state: &mut OpState,
+  #[serde] args: SpawnArgs,
+) -> Result<SpawnOutput, AnyError> {
+  if args.stdio.has_pty() {
+    return Err(type_error(
+      "Pty stdio is not supported for Deno.Command().outputSync().",
+    ));
+  }
+  let options = args.child_options()?;
+  let merges_output = args.merges_output()?;
+  let (mut command, _, _) =
+    create_command(state, args, "Deno.Command().outputSync()")?;
+  let merged_output = if merges_output {
+    Some(merge_output(&mut command)?)
+  } else {
+    None
+  };
+  let spawned_at = Instant::now();
+  let mut child = command.spawn().with_context(|| {
+    format!(
+      "Failed to spawn '{}'",
+      command.get_program().to_string_lossy()
+    )
+  })?;
+  // Closes our write ends of a merged output pipe, so that reading it ends
+  // when the child's do.
+  drop(command);
+
+  let stdout = match merged_output {
+    Some(read) => Some(read_to_end_in_background(read)),
+    None => child.stdout.take().map(read_to_end_in_background),
+  };
+  let stderr = child.stderr.take().map(read_to_end_in_background);
+  let status = wait_child_sync(
+    &mut child,
+    spawned_at,
+    options.process_group,
+    &options.termination,
+  )?;
+
+  Ok(SpawnOutput {
+    status,
+    stdout: join_output(stdout)?,
+    stderr: join_output(stderr)?,
+  })
+}
+
+#[op2(async)]
+#[serde]
+async fn op_spawn_read_line(
+  state: Rc<RefCell<OpState>>,
+  #[smi] rid: ResourceId,
+) -> Result<Option<String>, AnyError> {
+  let resource = state
+    .borrow()
+    .resource_table
+    .get::<MergedOutputResource>(rid)?;
+  resource.read_line().await
+}
+
+/// Sends `signal` to the child only, even if it leads a process group.
+#[op2(fast)]
+fn op_host_spawn_kill(
+  state: &mut OpState,
+  #[smi] rid: ResourceId,
+  #[string] signal: String,
+) -> Result<(), AnyError> {
+  spawn_kill(state, rid, &signal, false)
+}
+
+/// Sends `signal` to every process in the process group the child leads.
+#[op2(fast)]
+fn op_spawn_kill_group(
+  state: &mut OpState,
+  #[smi] rid: ResourceId,
+  #[string] signal: String,
+) -> Result<(), AnyError> {
+  spawn_kill(state, rid, &signal, true)
+}
+
+fn spawn_kill(
+  state: &mut OpState,
+  rid: ResourceId,
+  signal: &str,
+  group: bool,
+) -> Result<(), AnyError> {
+  if let Ok(child_resource) = state.resource_table.get::<ChildResource>(rid) {
+    if group && !child_resource.process_group {
+      return Err(type_error(
+        "Child process was not spawned in its own process group.",
+      ));
+    }
+    return kill_child(child_resource.pid, group, signal);
+  }
+  Err(type_error("Child process has already terminated."))
+}
+
+/// Sends `signal` to the child with `pid`, or with `group` to the process
+/// group it leads.
+fn kill_child(pid: u32, group: bool, signal: &str) -> Result<(), AnyError> {
+  #[cfg(unix)]
+  if group {
+    return kill_process_group(pid as i32, signal);
+  }
+  #[cfg(not(unix))]
+  let _ = group;
+  kill(pid as i32, signal)
+}
+
+#[cfg(unix)]
+fn kill_process_group(pgid: i32, signal: &str) -> Result<(), AnyError> {
+  let signo = signal_str_to_int(signal)?;
+  use nix::sys::signal::killpg;
+  use nix::sys::signal::Signal;
+  use nix::unistd::Pid;
+  let sig = Signal::try_from(signo)?;
+  killpg(Pid::from_raw(pgid), Option::Some(sig)).map_err(AnyError::from)
+}
+
+#[cfg(unix)]
+fn kill(pid: i32, signal: &str) -> Result<(), AnyError> {
+  let signo = signal_str_to_int(signal)?;
+  use nix::sys::signal::kill as unix_kill;
+  use nix::sys::signal::Signal;
+  use nix::unistd::Pid;
+  let sig = Signal::try_from(signo)?;
+  unix_kill(Pid::from_raw(pid), Option::Some(sig)).map_err(AnyError::from)
+}
+
+#[cfg(not(unix))]
+fn kill(pid: i32, signal: &str) -> Result<(), AnyError> {
+  use std::io::Error;
+  use std::io::ErrorKind::NotFound;
+  use winapi::shared::minwindef::DWORD;
+  use winapi::shared::minwindef::FALSE;
+  use winapi::shared::minwindef::TRUE;
+  use winapi::shared::winerror::ERROR_INVALID_PARAMETER;
+  use winapi::um::errhandlingapi::GetLastError;
+  use winapi::um::handleapi::CloseHandle;
+  use winapi::um::processthreadsapi::OpenProcess;
+  use winapi::um::processthreadsapi::TerminateProcess;
+  use winapi::um::winnt::PROCESS_TERMINATE;
+
+  if !matches!(signal, "SIGKILL" | "SIGTERM") {
+    Err(type_error(format!("Invalid signal: {signal}")))
+  } else if pid <= 0 {
+    Err(type_error("Invalid pid"))
+  } else {
+    let handle =
+      // SAFETY: winapi call
+      unsafe { OpenProcess(PROCESS_TERMINATE, FALSE, pid as DWORD) };
+
+    if handle.is_null() {
+      // SAFETY: winapi call
+      let err = match unsafe { GetLastError() } {
+        ERROR_INVALID_PARAMETER => Error::from(NotFound), // Invalid `pid`.
+        errno => Error::from_raw_os_error(errno as i32),
+      };
+      Err(err.into())
+    } else {
+      // SAFETY: winapi calls
+      unsafe {
+        let is_terminated = TerminateProcess(handle, 1);
+        CloseHandle(handle);
+        match is_terminated {
+          FALSE => Err(Error::last_os_error().into()),
+          TRUE => Ok(()),
+          _ => unreachable!(),
+        }
+      }
+    }
+  }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+  use super::*;
+  use deno_core::serde_json::json;
+  use deno_core::serde_json::Value;
+
+  fn spawn_args(options: Value) -> SpawnArgs {
+    let mut args = json!({
+      "cmd": "sh",
+      "args": [],
+      "clearEnv": false,
+      "env": [],
+      "stdin": "null",
+      "stdout": "piped",
+      "stderr": "piped",
+    });
+    args
+      .as_object_mut()
+      .unwrap()
+      .extend(options.as_object().unwrap().clone());
+    serde_json::from_value(args).unwrap()
+  }
+
+  #[test]
+  fn kill_on_drop_is_opt_in() {
+    let options = spawn_args(json!({})).child_options().unwrap();
+    assert!(!options.kill_on_drop);
+    let options = spawn_args(json!({ "killOnDrop": true }))
+      .child_options()
+      .unwrap();
+    assert!(options.kill_on_drop);
+  }
+
+  #[test]
+  fn rlimits_apply_to_the_child() {
+    let rlimits = spawn_args(json!({ "rlimits": { "openFiles": 64 } })).rlimits;
+    let mut command = std::process::Command::new("sh");
+    command.args(["-c", "ulimit -n"]);
+    // SAFETY: `setrlimit` is async-signal-safe.
+    unsafe {
+      command.pre_exec(move || rlimits.apply());
+    }
+    let output = command.output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "64");
+  }
+
+  #[test]
+  fn killing_the_process_group_reaches_grandchildren() {
+    let mut child = std::process::Command::new("sh")
+      .args(["-c", "sleep 30 & sleep 30"])
+      .stdout(std::process::Stdio::piped())
+      .process_group(0)
+      .spawn()
+      .unwrap();
+    kill_child(child.id(), true, "SIGTERM").unwrap();
+    // Ends once the backgrounded `sleep`, which shares the pipe, is gone.
+    let mut output = String::new();
+    child
+      .stdout
+      .take()
+      .unwrap()
+      .read_to_string(&mut output)
+      .unwrap();
+    assert_eq!(child.wait().unwrap().signal(), Some(libc::SIGTERM));
+  }
+
+  #[tokio::test]
+  async fn merged_output_keeps_the_order_of_writes() {
+    let mut command = std::process::Command::new("sh");
+    command.args(["-c", "echo one; echo two >&2; echo three"]);
+    let read = merge_output(&mut command).unwrap();
+    let mut child = command.spawn().unwrap();
+    drop(command);
+
+    let output = Rc::new(MergedOutputResource::new(read).unwrap());
+    let mut lines = vec![];
+    while let Some(line) = output.clone().read_line().await.unwrap() {
+      lines.push(line);
+    }
+    assert_eq!(lines, ["one", "two", "three"]);
+    assert!(child.wait().unwrap().success());
+  }
+
+  #[cfg(target_os = "linux")]
+  #[tokio::test]
+  async fn usage_is_that_of_the_reaped_child_alone() {
+    let spawned_at = Instant::now();
+    let mut idle = std::process::Command::new("sleep")
+      .arg("0.2")
+      .spawn()
+      .unwrap();
+    let mut busy = std::process::Command::new("sh")
+      .args(["-c", "i=0; while [ $i -lt 100000 ]; do i=$((i + 1)); done"])
+      .spawn()
+      .unwrap();
+    let busy_exit = wait_exit(&mut busy, spawned_at).await.unwrap();
+    let idle_exit = wait_exit(&mut idle, spawned_at).await.unwrap();
+    assert!(busy_exit.status.success());
+    assert!(idle_exit.status.success());
+
+    let busy_usage = busy_exit.usage.unwrap();
+    let idle_usage = idle_exit.usage.unwrap();
+    let cpu_time =
+      |usage: &ChildUsage| usage.user_cpu_time + usage.system_cpu_time;
+    assert!(cpu_time(&busy_usage) > cpu_time(&idle_usage));
+    assert!(busy_usage.max_rss > 0);
+    assert!(busy_usage.wall_time > 0.0);
+  }
+}
@@ -0,0 +1,178 @@
+//! Which parts of the global scope a runtime is bootstrapped with.
+//!
+//! `js/99_main.js` reads the profile with `op_bootstrap_profile` while
+//! bootstrapping the main or a worker runtime, after the full `Deno`
+//! namespace and globals have been set up, and removes or adds to them.
+//! APIs that are removed this way should not stay reachable through their
+//! ops either, `BootstrapProfile::denied_ops` lists which ops to filter out.
+
+use std::collections::BTreeMap;
+
+use deno_core::op2;
+use serde::Serialize;
+use deno_core::serde_json::Value;
+use deno_core::OpState;
+
+use crate::HostExtension;
+
+/// Ops and the APIs that use them, as `Deno.<name>` or a global name. An op
+/// is only denied once every API using it is excluded. Each op is registered
+/// by `deno_process`, `deno_tty` or the `process` host extension.
+const API_OPS: &[(&str, &[&str])] = &[
+  ("op_run", &["Deno.run"]),
+  ("op_run_status", &["Deno.run"]),
+  ("op_kill", &["Deno.run", "Deno.kill"]),
+  ("op_spawn_child", &["Deno.Command"]),
+  ("op_spawn_wait", &["Deno.Command"]),
+  ("op_spawn_wait_cancelable", &["Deno.Command"]),
+  ("op_spawn_sync", &["Deno.Command"]),
+  ("op_spawn_kill", &["Deno.Command"]),
+  ("op_spawn_kill_group", &["Deno.Command"]),
+  ("op_spawn_read_line", &["Deno.Command"]),
+  ("op_pty_resize", &["Deno.Command"]),
+  ("op_read_line_prompt", &["prompt", "alert", "confirm"]),
+];
+
+/// Ops of the deprecated `Deno.run()`, see `BootstrapProfile::deprecated_run`.
+/// `op_kill` is not one of them, as it also backs `Deno.kill()`.
+const DEPRECATED_RUN_OPS: &[&str] = &["op_run", "op_run_status"];
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BootstrapProfile {
+  /// Properties removed from the `Deno` namespace, e.g. `"run"`.
+  pub deno_exclude: Vec<String>,
+  /// Globals removed from `globalThis`, e.g. `"prompt"`.
+  pub global_exclude: Vec<String>,
+  /// Whether the main runtime defines `window`. Workers never do.
+  pub window: bool,
+  /// Whether `Deno.run()` works. When disabled it stays defined but throws
+  /// `NotSupported`, and its ops are denied. Always disabled without the
+  /// `deprecated_run` feature, whose absence disables its ops.
+  pub deprecated_run: bool,
+  /// Globals defined by the host. Each value is deeply frozen and defined
+  /// as a non-writable, non-configurable property of `globalThis`.
+  pub host_globals: BTreeMap<String, Value>,
+}
+
+impl Default for BootstrapProfile {
+  /// The full Deno global scope.
+  fn default() -> Self {
+    Self {
+      deno_exclude: vec![],
+      global_exclude: vec![],
+      window: true,
+      deprecated_run: true,
+      host_globals: BTreeMap::new(),
+    }
+  }
+}
+
+impl BootstrapProfile {
+  /// For plugin sandboxes: no subprocesses, no interactive prompts and no
+  /// `window`.
+  pub fn sandbox() -> Self {
+    Self {
+      deno_exclude: ["run", "Command", "ChildProcess", "kill"]
+        .map(String::from)
+        .to_vec(),
+      global_exclude: ["prompt", "alert", "confirm"].map(String::from).to_vec(),
+      window: false,
+      deprecated_run: false,
+      host_globals: BTreeMap::new(),
+    }
+  }
+
+  pub fn host_global(mut self, name: impl Into<String>, value: Value) -> Self {
+    self.host_globals.insert(name.into(), value);
+    self
+  }
+
+  fn excludes(&self, api: &str) -> bool {
+    match api.strip_prefix("Deno.") {
+      Some(name) => self.deno_exclude.iter().any(|n| n == name),
+      None => self.global_exclude.iter().any(|n| n == api),
+    }
+  }
+
+  /// Ops that only the excluded APIs use.
+  pub fn denied_ops(&self) -> Vec<&'static str> {
+    API_OPS
+      .iter()
+      .filter(|(op, apis)| {
+        apis.iter().all(|api| self.excludes(api))
+          || (!self.deprecated_run && DEPRECATED_RUN_OPS.contains(op))
+      })
+      .map(|(op, _)| *op)
+      .collect()
+  }
+}
+
+pub fn extension() -> HostExtension {
+  HostExtension::new("bootstrap_profile").ops([op_bootstrap_profile::DECL])
+}
+
+#[op2]
+#[serde]
+fn op_bootstrap_profile(state: &mut OpState) -> BootstrapProfile {
+  let mut profile = state
+    .try_borrow::<BootstrapProfile>()
+    .cloned()
+    .unwrap_or_default();
+  profile.deprecated_run &= cfg!(feature = "deprecated_run");
+  profile
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn full_profile_denies_nothing() {
+    assert!(BootstrapProfile::default().denied_ops().is_empty());
+  }
+
+  #[test]
+  fn sandbox_denies_subprocess_and_prompt_ops() {
+    let denied = BootstrapProfile::sandbox().denied_ops();
+    for op in ["op_spawn_child", "op_spawn_sync", "op_read_line_prompt"] {
+      assert!(denied.contains(&op), "{op} is not denied");
+    }
+  }
+
+  #[test]
+  fn op_is_denied_once_every_api_using_it_is_excluded() {
+    let profile = BootstrapProfile {
+      global_exclude: vec!["prompt".to_string(), "alert".to_string()],
+      ..Default::default()
+    };
+    assert!(!profile.denied_ops().contains(&"op_read_line_prompt"));
+
+    let profile = BootstrapProfile {
+      global_exclude: ["prompt", "alert", "confirm"].map(String::from).to_vec(),
+      ..Default::default()
+    };
+    assert_eq!(profile.denied_ops(), ["op_read_line_prompt"]);
+  }
+
+  #[test]
+  fn disabling_deprecated_run_keeps_deno_kill() {
+    let profile = BootstrapProfile {
+      deprecated_run: false,
+      ..Default::default()
+    };
+    assert_eq!(profile.denied_ops(), ["op_run", "op_run_status"]);
+  }
+
+  #[test]
+  fn api_ops_are_registered() {
+    let registered = crate::extensions::op_manifest(&[
+      deno_runtime::ops::process::deno_process::init_ops(),
+      deno_runtime::ops::tty::deno_tty::init_ops(),
+      crate::process::extension().for_runtime(),
+    ]);
+    for (op, _) in API_OPS {
+      assert!(registered.contains(op), "{op} is not registered");
+    }
+  }
+}
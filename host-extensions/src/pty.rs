@@ -0,0 +1,362 @@
+// Copyright 2018-2024 the Deno authors. All rights reserved. MIT license.
+
+//! Pseudo-terminals backing the `"pty"` stdio of `Deno.Command`.
+//!
+//! Every stdio of a child set to `"pty"` is connected to the slave side of a
+//! single pseudo-terminal, which also becomes the child's controlling
+//! terminal. The master side is returned to JS as a `PtyResource`, readable
+//! and writable like a pipe, that can be resized.
+
+use deno_core::error::type_error;
+use deno_core::error::AnyError;
+use deno_core::op2;
+use deno_core::OpState;
+use deno_core::ResourceId;
+use deno_runtime::ops::tty::ConsoleSize;
+use serde::Deserialize;
+
+#[cfg(unix)]
+use deno_core::CancelHandle;
+#[cfg(unix)]
+use deno_core::CancelTryFuture;
+#[cfg(unix)]
+use deno_core::RcRef;
+#[cfg(unix)]
+use deno_core::Resource;
+#[cfg(unix)]
+use std::borrow::Cow;
+#[cfg(unix)]
+use std::io;
+#[cfg(unix)]
+use std::os::fd::AsRawFd;
+#[cfg(unix)]
+use std::os::fd::FromRawFd;
+#[cfg(unix)]
+use std::os::fd::OwnedFd;
+#[cfg(unix)]
+use std::os::fd::RawFd;
+#[cfg(unix)]
+use std::os::unix::process::CommandExt;
+#[cfg(unix)]
+use std::rc::Rc;
+#[cfg(unix)]
+use tokio::io::unix::AsyncFd;
+
+/// Deserializes the `ptySize` of `Deno.Command`, as `ConsoleSize` can only
+/// be serialized.
+pub fn deserialize_size<'de, D>(
+  deserializer: D,
+) -> Result<Option<ConsoleSize>, D::Error>
+where
+  D: serde::Deserializer<'de>,
+{
+  #[derive(Deserialize)]
+  struct Size {
+    cols: u32,
+    rows: u32,
+  }
+  let size = Option::<Size>::deserialize(deserializer)?;
+  Ok(size.map(|Size { cols, rows }| ConsoleSize { cols, rows }))
+}
+
+/// The size of a pty when the spawn options don't specify one.
+pub const DEFAULT_SIZE: ConsoleSize = ConsoleSize { cols: 80, rows: 24 };
+
+#[cfg(unix)]
+pub struct Pty {
+  master: OwnedFd,
+  slave: OwnedFd,
+}
+
+#[cfg(unix)]
+impl Pty {
+  pub fn open(size: ConsoleSize) -> Result<Self, AnyError> {
+    let mut master = -1;
+    let mut slave = -1;
+    let mut winsize = winsize(size)?;
+    // SAFETY: the pointers outlive the call.
+    let ret = unsafe {
+      libc::openpty(
+        &mut master,
+        &mut slave,
+        std::ptr::null_mut(),
+        std::ptr::null_mut(),
+        &mut winsize,
+      )
+    };
+    if ret != 0 {
+      return Err(io::Error::last_os_error().into());
+    }
+    // SAFETY: `openpty` succeeded, so both fds are open and owned by nobody
+    // else.
+    let pty = unsafe {
+      Pty {
+        master: OwnedFd::from_raw_fd(master),
+        slave: OwnedFd::from_raw_fd(slave),
+      }
+    };
+    // Only the dups handed out by `stdio` may reach the child.
+    set_fd_flag(master, libc::F_GETFD, libc::F_SETFD, libc::FD_CLOEXEC)?;
+    set_fd_flag(slave, libc::F_GETFD, libc::F_SETFD, libc::FD_CLOEXEC)?;
+    Ok(pty)
+  }
+
+  /// A stdio connecting the child to the slave side.
+  pub fn stdio(&self) -> Result<std::process::Stdio, AnyError> {
+    Ok(self.slave.try_clone()?.into())
+  }
+
+  /// Makes the slave the controlling terminal of the child. This requires
+  /// the child to lead a new session, so it also calls `setsid`.
+  pub fn set_controlling_terminal(&self, command: &mut std::process::Command) {
+    let slave = self.slave.as_raw_fd();
+    // SAFETY: `setsid` and `ioctl` are async-signal-safe, and `slave` stays
+    // open until the child has been spawned.
+    unsafe {
+      command.pre_exec(move || {
+        if libc::setsid() == -1 {
+          return Err(io::Error::last_os_error());
+        }
+        if libc::ioctl(slave, libc::TIOCSCTTY as _, 0) == -1 {
+          return Err(io::Error::last_os_error());
+        }
+        Ok(())
+      });
+    }
+  }
+
+  /// Adds the master side to the resource table once the child has been
+  /// spawned. The parent's slave fd is closed, so that reads return EOF
+  /// after the child and its descendants have closed theirs.
+  pub fn spawned(self, state: &mut OpState) -> Result<ResourceId, AnyError> {
+    let Pty { master, slave } = self;
+    drop(slave);
+    Ok(state.resource_table.add(PtyResource::new(master)?))
+  }
+}
+
+#[cfg(not(unix))]
+pub enum Pty {}
+
+#[cfg(not(unix))]
+impl Pty {
+  pub fn open(_size: ConsoleSize) -> Result<Self, AnyError> {
+    Err(type_error("Pty stdio is not supported on Windows."))
+  }
+
+  pub fn stdio(&self) -> Result<std::process::Stdio, AnyError> {
+    match *self {}
+  }
+
+  pub fn spawned(self, _state: &mut OpState) -> Result<ResourceId, AnyError> {
+    match self {}
+  }
+}
+
+#[cfg(unix)]
+fn set_fd_flag(
+  fd: RawFd,
+  get: libc::c_int,
+  set: libc::c_int,
+  flag: libc::c_int,
+) -> Result<(), io::Error> {
+  // SAFETY: libc calls on an open fd.
+  unsafe {
+    let flags = libc::fcntl(fd, get);
+    if flags == -1 || libc::fcntl(fd, set, flags | flag) == -1 {
+      return Err(io::Error::last_os_error());
+    }
+  }
+  Ok(())
+}
+
+#[cfg(unix)]
+fn winsize(size: ConsoleSize) -> Result<libc::winsize, AnyError> {
+  let (Ok(ws_col), Ok(ws_row)) =
+    (u16::try_from(size.cols), u16::try_from(size.rows))
+  else {
+    return Err(type_error(format!(
+      "Pty size must be at most {} columns and rows, received {}x{}",
+      u16::MAX,
+      size.cols,
+      size.rows
+    )));
+  };
+  Ok(libc::winsize {
+    ws_row,
+    ws_col,
+    ws_xpixel: 0,
+    ws_ypixel: 0,
+  })
+}
+
+#[cfg(unix)]
+pub struct PtyResource {
+  master: AsyncFd<OwnedFd>,
+  cancel: CancelHandle,
+}
+
+#[cfg(unix)]
+impl PtyResource {
+  fn new(master: OwnedFd) -> Result<Self, AnyError> {
+    set_fd_flag(
+      master.as_raw_fd(),
+      libc::F_GETFL,
+      libc::F_SETFL,
+      libc::O_NONBLOCK,
+    )?;
+    Ok(Self {
+      master: AsyncFd::new(master)?,
+      cancel: Default::default(),
+    })
+  }
+
+  async fn read(self: Rc<Self>, data: &mut [u8]) -> Result<usize, AnyError> {
+    let cancel = RcRef::map(&self, |r| &r.cancel);
+    let nread = async {
+      loop {
+        let mut guard = self.master.readable().await?;
+        if let Ok(result) = guard.try_io(|master| {
+          // SAFETY: `data` is valid for writes of `data.len()` bytes.
+          let ret = unsafe {
+            libc::read(master.as_raw_fd(), data.as_mut_ptr().cast(), data.len())
+          };
+          if ret == -1 {
+            return Err(io::Error::last_os_error());
+          }
+          Ok(ret as usize)
+        }) {
+          break match result {
+            // Linux reports a hung up slave side as EIO rather than EOF.
+            Err(err) if err.raw_os_error() == Some(libc::EIO) => Ok(0),
+            result => result,
+          };
+        }
+      }
+    }
+    .try_or_cancel(cancel)
+    .await?;
+    Ok(nread)
+  }
+
+  async fn write(self: Rc<Self>, data: &[u8]) -> Result<usize, AnyError> {
+    let cancel = RcRef::map(&self, |r| &r.cancel);
+    let nwritten = async {
+      loop {
+        let mut guard = self.master.writable().await?;
+        if let Ok(result) = guard.try_io(|master| {
+          // SAFETY: `data` is valid for reads of `data.len()` bytes.
+          let ret = unsafe {
+            libc::write(master.as_raw_fd(), data.as_ptr().cast(), data.len())
+          };
+          if ret == -1 {
+            return Err(io::Error::last_os_error());
+          }
+          Ok(ret as usize)
+        }) {
+          break result;
+        }
+      }
+    }
+    .try_or_cancel(cancel)
+    .await?;
+    Ok(nwritten)
+  }
+
+  fn resize(&self, size: ConsoleSize) -> Result<(), AnyError> {
+    let winsize = winsize(size)?;
+    // SAFETY: `winsize` outlives the call.
+    if unsafe {
+      libc::ioctl(self.master.as_raw_fd(), libc::TIOCSWINSZ, &winsize)
+    } != 0
+    {
+      return Err(io::Error::last_os_error().into());
+    }
+    Ok(())
+  }
+}
+
+#[cfg(unix)]
+impl Resource for PtyResource {
+  deno_core::impl_readable_byob!();
+  deno_core::impl_writable!();
+
+  fn name(&self) -> Cow<str> {
+    "pty".into()
+  }
+
+  fn close(self: Rc<Self>) {
+    self.cancel.cancel();
+  }
+}
+
+/// Resizes a pty, which sends `SIGWINCH` to the foreground process group of
+/// the child.
+#[op2(fast)]
+pub fn op_pty_resize(
+  state: &mut OpState,
+  #[smi] rid: ResourceId,
+  cols: u32,
+  rows: u32,
+) -> Result<(), AnyError> {
+  #[cfg(unix)]
+  {
+    let resource = state.resource_table.get::<PtyResource>(rid)?;
+    resource.resize(ConsoleSize { cols, rows })
+  }
+  #[cfg(not(unix))]
+  {
+    let _ = (state, rid, cols, rows);
+    Err(deno_core::error::not_supported())
+  }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+  use super::*;
+
+  fn window_size(fd: &OwnedFd) -> ConsoleSize {
+    // SAFETY: all-zero is a valid `winsize`.
+    let mut winsize: libc::winsize = unsafe { std::mem::zeroed() };
+    // SAFETY: `winsize` outlives the call.
+    let ret =
+      unsafe { libc::ioctl(fd.as_raw_fd(), libc::TIOCGWINSZ, &mut winsize) };
+    assert_eq!(ret, 0, "{}", io::Error::last_os_error());
+    ConsoleSize {
+      cols: winsize.ws_col.into(),
+      rows: winsize.ws_row.into(),
+    }
+  }
+
+  #[tokio::test]
+  async fn resize_sets_the_window_size_of_the_slave() {
+    let Pty { master, slave } = Pty::open(DEFAULT_SIZE).unwrap();
+    assert_eq!(window_size(&slave), DEFAULT_SIZE);
+
+    let resource = PtyResource::new(master).unwrap();
+    let size = ConsoleSize {
+      cols: 132,
+      rows: 43,
+    };
+    resource.resize(size).unwrap();
+    assert_eq!(window_size(&slave), size);
+  }
+
+  #[tokio::test]
+  async fn sizes_beyond_u16_are_rejected() {
+    let size = ConsoleSize {
+      cols: u16::MAX as u32 + 1,
+      rows: 24,
+    };
+    assert!(Pty::open(size).is_err());
+
+    let Pty { master, slave } = Pty::open(DEFAULT_SIZE).unwrap();
+    let resource = PtyResource::new(master).unwrap();
+    let error = resource.resize(size).unwrap_err();
+    assert_eq!(
+      deno_core::error::get_custom_error_class(&error),
+      Some("TypeError")
+    );
+    assert_eq!(window_size(&slave), DEFAULT_SIZE);
+  }
+}
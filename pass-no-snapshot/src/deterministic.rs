@@ -0,0 +1,120 @@
+use std::num::NonZeroI32;
+use std::sync::OnceLock;
+
+use deno_core::error::generic_error;
+use deno_core::error::AnyError;
+use deno_core::located_script_name;
+use deno_core::v8;
+use deno_core::FastString;
+use deno_core::JsRuntime;
+use host_extensions::clock::VirtualClock;
+
+/// Makes the randomness and time observed by JS depend only on these
+/// settings, so that the same input produces the same output on every run.
+///
+/// - `Math.random()` is seeded through V8's `--random-seed`, which is
+///   process-wide. The first runtime of the process decides the seed: a
+///   later runtime with another seed or without `Deterministic` fails to
+///   start, as does the first deterministic one if a `JsRuntime` was
+///   created before it. See `init_v8_flags`.
+/// - `crypto.getRandomValues()` and `crypto.randomUUID()` use an RNG seeded
+///   with `seed`.
+/// - `Date`, `performance.now()` and timers use a `VirtualClock` starting at
+///   `epoch_ms`, unless `RunOptions::virtual_clock` is set, and
+///   `TimersPermission::allow_hrtime` is denied.
+#[derive(Clone, Copy, Debug)]
+pub struct Deterministic {
+  /// Nonzero, because V8 treats a `--random-seed` of `0` as "seed from
+  /// entropy".
+  pub seed: NonZeroI32,
+  /// Milliseconds since the Unix epoch the clock starts at.
+  pub epoch_ms: f64,
+}
+
+impl Deterministic {
+  /// Seed of the RNG behind `crypto`.
+  pub fn rng_seed(&self) -> u64 {
+    self.seed.get() as u64
+  }
+
+  pub fn clock(&self) -> VirtualClock {
+    VirtualClock::new(self.epoch_ms)
+  }
+}
+
+/// How V8 was set up by the first runtime created in the process.
+#[derive(Clone, Copy)]
+enum V8RandomSeed {
+  /// Without `--random-seed`, for a runtime without `Deterministic`.
+  Unseeded,
+  Seeded(NonZeroI32),
+  /// `--random-seed` was set, but V8 had already been initialized by a
+  /// `JsRuntime` created elsewhere, so it has no effect.
+  Ignored(NonZeroI32),
+}
+
+static V8_RANDOM_SEED: OnceLock<V8RandomSeed> = OnceLock::new();
+
+/// Sets V8's flags before the first isolate of the process is created.
+///
+/// V8 flags are process-wide and read when V8 is initialized, so the first
+/// runtime decides the `--random-seed` of every isolate. Every later runtime
+/// has to ask for the same: one without `Deterministic` fails to start once
+/// `Math.random()` is seeded, and a deterministic one fails once V8 was set
+/// up with another seed or with none. Setting the seed also fails if any
+/// `JsRuntime` was created before, as V8 has read its flags by then.
+pub fn init_v8_flags(
+  deterministic: Option<&Deterministic>,
+) -> Result<(), AnyError> {
+  let requested = deterministic.map(|deterministic| deterministic.seed);
+  let setup = *V8_RANDOM_SEED.get_or_init(|| {
+    let Some(seed) = requested else {
+      return V8RandomSeed::Unseeded;
+    };
+    deno_core::v8_set_flags(vec![
+      "".to_string(),
+      format!("--random-seed={seed}"),
+    ]);
+    match math_random_is_seeded() {
+      true => V8RandomSeed::Seeded(seed),
+      false => V8RandomSeed::Ignored(seed),
+    }
+  });
+  match (requested, setup) {
+    (None, V8RandomSeed::Unseeded) => Ok(()),
+    (Some(requested), V8RandomSeed::Seeded(seed)) if requested == seed => {
+      Ok(())
+    }
+    (None, V8RandomSeed::Seeded(seed)) => Err(generic_error(format!(
+      "V8 was set up with --random-seed={seed} for a deterministic runtime, Math.random() would not be random in this one"
+    ))),
+    (Some(requested), V8RandomSeed::Seeded(seed)) => Err(generic_error(
+      format!(
+        "V8 was already set up with --random-seed={seed}, {requested} cannot be used in this process"
+      ),
+    )),
+    (Some(_), V8RandomSeed::Unseeded) => Err(generic_error(
+      "V8 was already set up without a random seed, create the deterministic runtime first",
+    )),
+    (_, V8RandomSeed::Ignored(seed)) => Err(generic_error(format!(
+      "V8 was initialized before --random-seed={seed} could be set, create the deterministic runtime before any other JsRuntime"
+    ))),
+  }
+}
+
+/// Whether V8 applies `--random-seed`, in which case every isolate starts
+/// `Math.random()` with the same value.
+fn math_random_is_seeded() -> bool {
+  let first_random = || {
+    let mut js_runtime = JsRuntime::new(Default::default());
+    let value = js_runtime
+      .execute_script(
+        located_script_name!(),
+        FastString::from("Math.random()".to_string()),
+      )
+      .unwrap();
+    let scope = &mut js_runtime.handle_scope();
+    v8::Local::new(scope, value).number_value(scope)
+  };
+  first_random() == first_random()
+}
@@ -0,0 +1,820 @@
+use std::cell::Cell;
+use std::future::Future;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
+
+use deno_core::error::generic_error;
+use deno_core::error::type_error;
+use deno_core::error::AnyError;
+use deno_core::error::JsError;
+use deno_core::futures::FutureExt;
+use deno_core::serde::de::DeserializeOwned;
+use deno_core::serde::Serialize;
+use deno_core::serde_v8;
+use deno_core::url::Url;
+use deno_core::v8;
+use deno_core::FastString;
+use deno_core::JsBuffer;
+use deno_core::JsRuntime;
+use deno_core::ModuleId;
+use deno_core::PollEventLoopOptions;
+use deno_core::ToJsBuffer;
+use deno_runtime::fmt_errors::format_js_error;
+use deno_runtime::worker::ExitCode;
+use deno_runtime::BootstrapOptions;
+use deno_web::BlobStore;
+use host_extensions::buffers::HostBuffers;
+use host_extensions::clock;
+use host_extensions::clock::VirtualClock;
+use host_extensions::extensions::check_snapshot_extensions;
+use host_extensions::extensions::extension_manifest;
+use host_extensions::extensions::extensions;
+use host_extensions::extensions::ExtensionMode;
+use host_extensions::extensions::ExtensionOptions;
+
+use crate::deterministic;
+use crate::exit;
+use crate::exit::ExitHandler;
+use crate::exit::ExitOutcome;
+use crate::exit::RunError;
+use crate::limits::LimitsWatchdog;
+use crate::module_loader::HostModuleLoader;
+use crate::op_filter::OpFilter;
+use crate::op_trace::OpTracer;
+use crate::permissions;
+use crate::permissions::ImportPermission;
+use crate::permissions::Permissions;
+use crate::worker::create_web_worker_callback;
+use crate::worker::SharedWorkerState;
+use crate::RunOptions;
+use crate::SNAPSHOT;
+
+/// A live isolate that Rust code can keep around and call into.
+///
+/// Unlike `run_js`, which runs a script to completion, a `Runtime` only
+/// drives the event loop as far as each call needs: until a module has
+/// evaluated, or until the promise returned by a JS function has settled.
+///
+/// ```ignore
+/// let mut runtime = Runtime::new(RunOptions::default())?;
+/// let id = runtime.load_side_module(&specifier, None).await?;
+/// let transform = runtime.get_export(id, "transform")?;
+/// let output: String = runtime.call_async(&transform, (code, id)).await?;
+/// ```
+pub struct Runtime {
+  js_runtime: JsRuntime,
+  main_module: Url,
+  exit_code_from_js: Rc<Cell<Option<i32>>>,
+  watchdog: LimitsWatchdog,
+  tracer: Rc<OpTracer>,
+  buffers: HostBuffers,
+  virtual_clock: Option<VirtualClock>,
+}
+
+impl Runtime {
+  /// Fails if `SNAPSHOT` was created with a different extension list.
+  pub fn new(options: RunOptions) -> Result<Self, AnyError> {
+    let RunOptions {
+      limits,
+      op_filter,
+      op_trace,
+      host_state,
+      bootstrap_profile,
+      deterministic,
+      virtual_clock,
+      mut permissions,
+    } = options;
+    let op_filter = bootstrap_profile
+      .denied_ops()
+      .into_iter()
+      .fold(op_filter, OpFilter::deny_op);
+    // Through `HostState`, so that web workers are bootstrapped with the same
+    // profile.
+    let mut host_state = host_state.put(bootstrap_profile);
+    deterministic::init_v8_flags(deterministic.as_ref())?;
+    let virtual_clock = virtual_clock.or(deterministic.map(|d| d.clock()));
+    if let Some(clock) = &virtual_clock {
+      host_state = host_state.put(clock.clone());
+    }
+    let seed = deterministic.map(|deterministic| deterministic.rng_seed());
+    let exe_path = std::env::current_exe()
+      .unwrap()
+      .parent()
+      .unwrap()
+      .to_path_buf();
+    let main_module = Url::from_file_path(exe_path).unwrap();
+    let fs = std::sync::Arc::new(deno_fs::RealFs);
+    permissions.hrtime &= deterministic.is_none();
+    let container = permissions.container()?;
+    let import = ImportPermission::new(permissions.import.clone());
+
+    let bootstrap_options = BootstrapOptions {
+      enable_op_summary_metrics: op_trace.enable_op_summary_metrics,
+      ..Default::default()
+    };
+
+    let blob_store = Arc::new(BlobStore::default());
+    let broadcast_channel =
+      deno_broadcast_channel::InMemoryBroadcastChannel::default();
+
+    let web_worker_callback = create_web_worker_callback(
+      Arc::new(SharedWorkerState {
+        // node_resolver: todo!(),
+        blob_store: blob_store.clone(),
+        broadcast_channel: broadcast_channel.clone(),
+        shared_array_buffer_store: Default::default(),
+        compiled_wasm_module_store: Default::default(),
+        fs: fs.clone(),
+        argv: vec![],
+        argv0: Some("".to_string()),
+        skip_op_registration: false,
+        maybe_root_package_json_deps: None,
+        limits: limits.clone(),
+        op_filter: op_filter.clone(),
+        host_state: host_state.clone(),
+        seed,
+        permissions: permissions.clone(),
+      }),
+      Default::default(),
+    );
+
+    let exit_code_from_js = Rc::new(Cell::new(None));
+    let exit_handler = {
+      let exit_code_from_js = exit_code_from_js.clone();
+      ExitHandler(Rc::new(move |code| exit_code_from_js.set(Some(code))))
+    };
+    let buffers = HostBuffers::default();
+
+    let mut extensions = extensions::<Permissions>(
+      ExtensionMode::FromSnapshot,
+      ExtensionOptions {
+        main_module: main_module.clone(),
+        blob_store,
+        broadcast_channel,
+        fs,
+        create_web_worker_cb: web_worker_callback,
+        format_js_error_fn: Some(Arc::new(format_js_error)),
+        host_state,
+        seed,
+        runtime: None,
+        namespace: host_extensions::DEFAULT_NAMESPACE,
+      },
+    );
+    let snapshot_extensions = extension_manifest(&extensions);
+    extensions.extend([
+      exit::deno_host_exit::init_ops(exit_handler),
+      permissions::deno_host_permissions::init_ops(),
+      op_filter.extension(),
+    ]);
+
+    let tracer = OpTracer::new(op_trace);
+
+    let runtime_options = deno_core::RuntimeOptions {
+      module_loader: Some(Rc::new(HostModuleLoader::new(import.clone()))),
+      is_main: true,
+      startup_snapshot: Some(SNAPSHOT),
+      create_params: limits.create_params(),
+      op_metrics_factory_fn: tracer.op_metrics_factory_fn(),
+      extensions,
+      ..Default::default()
+    };
+
+    let mut js_runtime = JsRuntime::new(runtime_options);
+    check_snapshot_extensions(&mut js_runtime, &snapshot_extensions)?;
+    let watchdog = limits.install(&mut js_runtime);
+
+    {
+      let op_state = &mut js_runtime.op_state();
+      let mut state = op_state.borrow_mut();
+      state.put(bootstrap_options.clone());
+      state.put(permissions);
+      state.put(import);
+      state.put(container);
+      state.put(buffers.clone());
+      #[cfg(feature = "node")]
+      if let Some(node_ipc_fd) = bootstrap_options.node_ipc_fd {
+        state.put(deno_node::ChildPipeFd(node_ipc_fd));
+      }
+    }
+    {
+      let _timeout = watchdog.time_execution();
+      bootstrap(&mut js_runtime, &bootstrap_options)?;
+    }
+
+    Ok(Self {
+      js_runtime,
+      main_module,
+      exit_code_from_js,
+      watchdog,
+      tracer,
+      buffers,
+      virtual_clock,
+    })
+  }
+
+  pub fn main_module(&self) -> &Url {
+    &self.main_module
+  }
+
+  pub fn js_runtime(&mut self) -> &mut JsRuntime {
+    &mut self.js_runtime
+  }
+
+  /// Loads `code` as the main module and evaluates it, driving the event
+  /// loop until evaluation (including top-level await) has finished.
+  pub async fn load_main_module(
+    &mut self,
+    code: String,
+  ) -> Result<ModuleId, AnyError> {
+    let _timeout = self.watchdog.time_execution();
+    let module_id = self
+      .js_runtime
+      .load_main_es_module_from_code(&self.main_module, FastString::from(code))
+      .await?;
+    self.evaluate(module_id).await?;
+    Ok(module_id)
+  }
+
+  /// Loads and evaluates a side module, from `code` if given or else through
+  /// the module loader.
+  pub async fn load_side_module(
+    &mut self,
+    specifier: &Url,
+    code: Option<String>,
+  ) -> Result<ModuleId, AnyError> {
+    let _timeout = self.watchdog.time_execution();
+    let module_id = match code {
+      Some(code) => {
+        self
+          .js_runtime
+          .load_side_es_module_from_code(specifier, FastString::from(code))
+          .await?
+      }
+      None => self.js_runtime.load_side_es_module(specifier).await?,
+    };
+    self.evaluate(module_id).await?;
+    Ok(module_id)
+  }
+
+  async fn evaluate(&mut self, module_id: ModuleId) -> Result<(), AnyError> {
+    let evaluation = self.js_runtime.mod_evaluate(module_id);
+    match self.virtual_clock.clone() {
+      // So that top-level await can wait for a virtual timer.
+      Some(clock) => self.with_virtual_timers(&clock, evaluation).await,
+      None => {
+        self
+          .js_runtime
+          .with_event_loop_future(evaluation, PollEventLoopOptions::default())
+          .await
+      }
+    }
+  }
+
+  /// Buffers queued for, or given back by, JS through `takeBuffer` and
+  /// `giveBuffer`.
+  pub fn buffers(&self) -> &HostBuffers {
+    &self.buffers
+  }
+
+  /// Wraps `data` in a `Uint8Array` backed by its allocation, for passing
+  /// to JS without a copy. See `HostBuffers::send` for when `data` is
+  /// copied after all.
+  pub fn to_js_buffer(&mut self, data: Vec<u8>) -> v8::Global<v8::Value> {
+    let scope = &mut self.js_runtime.handle_scope();
+    let value = serde_v8::to_v8(scope, ToJsBuffer::from(data)).unwrap();
+    v8::Global::new(scope, value)
+  }
+
+  /// Borrows the backing store of a `Uint8Array` returned by JS.
+  pub fn from_js_buffer(
+    &mut self,
+    value: &v8::Global<v8::Value>,
+  ) -> Result<JsBuffer, AnyError> {
+    let scope = &mut self.js_runtime.handle_scope();
+    let value = v8::Local::new(scope, value);
+    Ok(serde_v8::from_v8(scope, value)?)
+  }
+
+  /// Looks up the export `name` of an evaluated module.
+  pub fn get_export(
+    &mut self,
+    module_id: ModuleId,
+    name: &str,
+  ) -> Result<v8::Global<v8::Value>, AnyError> {
+    let namespace = self.js_runtime.get_module_namespace(module_id)?;
+    let scope = &mut self.js_runtime.handle_scope();
+    let namespace = v8::Local::new(scope, namespace);
+    let key = v8::String::new(scope, name).unwrap();
+    match namespace.get(scope, key.into()) {
+      Some(value) if !value.is_undefined() => Ok(v8::Global::new(scope, value)),
+      _ => Err(type_error(format!("Module does not export \"{name}\""))),
+    }
+  }
+
+  /// Calls `function` with `args` and deserializes its result, awaiting it
+  /// first if it is a promise.
+  ///
+  /// `args` is serialized with `serde_v8`. A tuple or sequence is spread
+  /// into positional arguments, any other value is passed as the only
+  /// argument. `ToJsBuffer` arguments and a `JsBuffer` result are passed
+  /// without copying, see `to_js_buffer`.
+  pub async fn call_async<Args, Ret>(
+    &mut self,
+    function: &v8::Global<v8::Value>,
+    args: Args,
+  ) -> Result<Ret, AnyError>
+  where
+    Args: Serialize,
+    Ret: DeserializeOwned,
+  {
+    let _timeout = self.watchdog.time_execution();
+    let (function, args) = {
+      let scope = &mut self.js_runtime.handle_scope();
+      let function = v8::Local::new(scope, function);
+      let function = v8::Local::<v8::Function>::try_from(function)
+        .map_err(|_| type_error("Export is not a function"))?;
+      let args = serde_v8::to_v8(scope, args)?;
+      let args = match v8::Local::<v8::Array>::try_from(args) {
+        Ok(array) => (0..array.length())
+          .map(|i| {
+            let arg = array.get_index(scope, i).unwrap();
+            v8::Global::new(scope, arg)
+          })
+          .collect(),
+        Err(_) => vec![v8::Global::new(scope, args)],
+      };
+      (v8::Global::new(scope, function), args)
+    };
+
+    let call = self.js_runtime.call_with_args(&function, &args);
+    let result = match self.virtual_clock.clone() {
+      Some(clock) => {
+        let value = self.with_virtual_timers(&clock, call).await?;
+        let promise = self.js_runtime.resolve(value);
+        self.with_virtual_timers(&clock, promise).await?
+      }
+      None => {
+        self
+          .js_runtime
+          .with_event_loop_promise(call, PollEventLoopOptions::default())
+          .await?
+      }
+    };
+
+    let scope = &mut self.js_runtime.handle_scope();
+    let result = v8::Local::new(scope, result);
+    Ok(serde_v8::from_v8(scope, result)?)
+  }
+
+  /// Runs the event loop until there is no more pending work.
+  pub async fn run_event_loop(&mut self) -> Result<(), AnyError> {
+    let _timeout = self.watchdog.time_execution();
+    self
+      .js_runtime
+      .run_event_loop(PollEventLoopOptions::default())
+      .await
+  }
+
+  /// The clock set in `RunOptions::virtual_clock` or by `Deterministic`.
+  pub fn virtual_clock(&self) -> Option<&VirtualClock> {
+    self.virtual_clock.as_ref()
+  }
+
+  /// Advances the virtual clock by `duration`, firing the timers that
+  /// become due in order, each at its due time. Like `tokio::time::advance`
+  /// for JS timers: no real time has to pass.
+  pub async fn advance(&mut self, duration: Duration) -> Result<(), AnyError> {
+    let _timeout = self.watchdog.time_execution();
+    let clock = self
+      .virtual_clock
+      .clone()
+      .ok_or_else(|| type_error("Runtime has no virtual clock"))?;
+    let until = clock.now() + duration.as_secs_f64() * 1000.0;
+    self.run_virtual_timers(&clock, Some(until)).await?;
+    clock.advance_to(until);
+    Ok(())
+  }
+
+  /// Runs the event loop and fires due timers until neither has work left,
+  /// moving the clock to the next timer as long as it is due by `until`.
+  async fn run_virtual_timers(
+    &mut self,
+    clock: &VirtualClock,
+    until: Option<f64>,
+  ) -> Result<(), AnyError> {
+    while self.step_virtual_timers(clock, until).await? {}
+    Ok(())
+  }
+
+  /// Runs the event loop until it is idle, then fires the earliest timer if
+  /// it is due or else moves the clock to it. Returns whether anything was
+  /// left to do.
+  async fn step_virtual_timers(
+    &mut self,
+    clock: &VirtualClock,
+    until: Option<f64>,
+  ) -> Result<bool, AnyError> {
+    self.run_event_loop().await?;
+    let timers = clock::run_due_timers(&mut self.js_runtime)?;
+    if timers.fired {
+      return Ok(true);
+    }
+    match timers.next {
+      Some(next) if until.map_or(true, |until| next <= until) => {
+        clock.advance_to(next);
+        Ok(true)
+      }
+      _ => Ok(false),
+    }
+  }
+
+  /// Like `JsRuntime::with_event_loop_future`, skipping ahead to the next
+  /// virtual timer whenever the event loop is idle and `future` has not
+  /// resolved yet.
+  async fn with_virtual_timers<T>(
+    &mut self,
+    clock: &VirtualClock,
+    future: impl Future<Output = Result<T, AnyError>>,
+  ) -> Result<T, AnyError> {
+    let mut future = std::pin::pin!(future);
+    loop {
+      tokio::select! {
+        biased;
+        result = &mut future => return result,
+        progress = self.step_virtual_timers(clock, None) => {
+          if !progress? {
+            break;
+          }
+        }
+      }
+    }
+    future.now_or_never().unwrap_or_else(|| {
+      Err(generic_error(
+        "Promise resolution is still pending but the event loop and the \
+         virtual timers have already resolved.",
+      ))
+    })
+  }
+
+  /// Runs the event loop to completion, skipping ahead to each virtual
+  /// timer instead of waiting for it.
+  async fn run_to_completion(&mut self) -> Result<(), AnyError> {
+    match self.virtual_clock.clone() {
+      Some(clock) => self.run_virtual_timers(&clock, None).await,
+      None => self.run_event_loop().await,
+    }
+  }
+
+  /// Runs `code` as the main module to completion and reports how it ended.
+  pub async fn execute_main_module(
+    &mut self,
+    code: String,
+  ) -> Result<ExitOutcome, RunError> {
+    let _timeout = self.watchdog.time_execution();
+    let result = match self
+      .js_runtime
+      .load_main_es_module_from_code(&self.main_module, FastString::from(code))
+      .await
+    {
+      Ok(module_id) => {
+        let evaluation = self.js_runtime.mod_evaluate(module_id);
+        match self.run_to_completion().await {
+          Ok(()) => evaluation.await,
+          Err(err) => Err(err),
+        }
+      }
+      Err(err) if err.downcast_ref::<JsError>().is_some() => Err(err),
+      Err(err) => return Err(RunError::Load(err)),
+    };
+
+    if let Some(outcome) = self.terminated() {
+      return Ok(outcome);
+    }
+
+    match result {
+      Ok(()) => {
+        let op_state = self.js_runtime.op_state();
+        let code = op_state.borrow().borrow::<ExitCode>().get();
+        Ok(ExitOutcome::Completed { code })
+      }
+      Err(err) => match err.downcast::<JsError>() {
+        Ok(js_error) => {
+          Ok(ExitOutcome::UncaughtException(format_js_error(&js_error)))
+        }
+        Err(err) => Err(RunError::Runtime(err)),
+      },
+    }
+  }
+
+  /// If the isolate has been terminated, the reason why.
+  ///
+  /// `Deno.exit()` and the resource limits both terminate the isolate, which
+  /// surfaces from any pending call as a generic "execution terminated"
+  /// error. Once a `Deno.exit()` has been reported here its termination is
+  /// cancelled, so the runtime can run JS again.
+  pub fn terminated(&mut self) -> Option<ExitOutcome> {
+    if let Some(code) = self.exit_code_from_js.take() {
+      self.js_runtime.v8_isolate().cancel_terminate_execution();
+      return Some(ExitOutcome::Exited { code });
+    }
+    if let Some(exceeded) = self.watchdog.violation() {
+      return Some(ExitOutcome::Terminated(Some(exceeded)));
+    }
+    if self.js_runtime.v8_isolate().is_execution_terminating() {
+      return Some(ExitOutcome::Terminated(None));
+    }
+    None
+  }
+
+  /// Prints the op metrics summary, if it was enabled in `RunOptions`.
+  pub fn print_op_summary(&self) {
+    self.tracer.print_summary();
+  }
+}
+
+/// Calls `bootstrapMainRuntime` of the snapshotted `99_main.js`, which sets
+/// up the global scope: `Deno`, the web globals, the bootstrap profile and
+/// the virtual clock.
+fn bootstrap(
+  js_runtime: &mut JsRuntime,
+  options: &BootstrapOptions,
+) -> Result<(), AnyError> {
+  let scope = &mut js_runtime.handle_scope();
+  let context = scope.get_current_context();
+  let global = context.global(scope);
+  let bootstrap_fn = ["bootstrap", "mainRuntime"]
+    .iter()
+    .try_fold(v8::Local::<v8::Value>::from(global), |object, key| {
+      let key = v8::String::new(scope, key).unwrap();
+      object.to_object(scope)?.get(scope, key.into())
+    })
+    .and_then(|value| v8::Local::<v8::Function>::try_from(value).ok())
+    .ok_or_else(|| type_error("Snapshot has no globalThis.bootstrap"))?;
+  let args = options.as_v8(scope);
+  let undefined = v8::undefined(scope);
+  let scope = &mut v8::TryCatch::new(scope);
+  bootstrap_fn.call(scope, undefined.into(), &[args]);
+  match scope.exception() {
+    Some(exception) => Err(JsError::from_v8_exception(scope, exception).into()),
+    None => Ok(()),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::testing::eval;
+  use host_extensions::extensions::check_snapshot_ops;
+  use host_extensions::profile::BootstrapProfile;
+
+  fn runtime_with_clock(clock: &VirtualClock) -> Runtime {
+    Runtime::new(RunOptions {
+      virtual_clock: Some(clock.clone()),
+      ..Default::default()
+    })
+    .unwrap()
+  }
+
+  #[tokio::test]
+  async fn top_level_await_skips_ahead_to_virtual_timers() {
+    let clock = VirtualClock::new(1_000.0);
+    let mut runtime = runtime_with_clock(&clock);
+    let specifier = Url::parse("file:///test/tla.js").unwrap();
+    let code = r#"
+      await new Promise((resolve) => setTimeout(resolve, 60_000));
+      globalThis.resumedAt = Date.now();
+    "#;
+    runtime
+      .load_side_module(&specifier, Some(code.to_string()))
+      .await
+      .unwrap();
+    let resumed_at: f64 = eval(&mut runtime, "return globalThis.resumedAt;")
+      .await
+      .unwrap();
+    assert_eq!(resumed_at, 61_000.0);
+    assert_eq!(clock.now(), 61_000.0);
+  }
+
+  #[tokio::test]
+  async fn advance_fires_timers_at_their_due_time() {
+    let clock = VirtualClock::new(0.0);
+    let mut runtime = runtime_with_clock(&clock);
+    let () = eval(
+      &mut runtime,
+      r#"
+        globalThis.fired = [];
+        setTimeout(() => fired.push(Date.now()), 100);
+        setTimeout(() => fired.push(Date.now()), 30);
+      "#,
+    )
+    .await
+    .unwrap();
+
+    runtime.advance(Duration::from_millis(50)).await.unwrap();
+    let fired: Vec<f64> = eval(&mut runtime, "return fired;").await.unwrap();
+    assert_eq!(fired, [30.0]);
+    assert_eq!(clock.now(), 50.0);
+
+    runtime.advance(Duration::from_millis(50)).await.unwrap();
+    let fired: Vec<f64> = eval(&mut runtime, "return fired;").await.unwrap();
+    assert_eq!(fired, [30.0, 100.0]);
+  }
+
+  #[tokio::test]
+  async fn run_due_timers_fires_only_due_timers() {
+    let clock = VirtualClock::new(0.0);
+    let mut runtime = runtime_with_clock(&clock);
+    let timers = clock::run_due_timers(runtime.js_runtime()).unwrap();
+    assert!(!timers.fired);
+    assert_eq!(timers.next, None);
+
+    let () = eval(
+      &mut runtime,
+      "globalThis.fired = false; setTimeout(() => fired = true, 100);",
+    )
+    .await
+    .unwrap();
+    let timers = clock::run_due_timers(runtime.js_runtime()).unwrap();
+    assert!(!timers.fired);
+    assert_eq!(timers.next, Some(100.0));
+
+    clock.advance(Duration::from_millis(100));
+    let timers = clock::run_due_timers(runtime.js_runtime()).unwrap();
+    assert!(timers.fired);
+    let fired: bool = eval(&mut runtime, "return fired;").await.unwrap();
+    assert!(fired);
+  }
+
+  #[tokio::test]
+  async fn snapshot_mismatch_is_rejected() {
+    let mut runtime = Runtime::new(RunOptions::default()).unwrap();
+    let extensions = vec!["deno_webidl/0".to_string()];
+    let err = check_snapshot_extensions(runtime.js_runtime(), &extensions)
+      .unwrap_err();
+    assert!(err.to_string().contains("[deno_webidl/0]"), "{err}");
+    let err = check_snapshot_ops(runtime.js_runtime(), &["op_bogus"])
+      .unwrap_err();
+    assert!(err.to_string().contains("op_bogus"), "{err}");
+  }
+
+  #[tokio::test]
+  async fn buffers_round_trip_through_js() {
+    let mut runtime = Runtime::new(RunOptions::default()).unwrap();
+    let id = runtime.buffers().send(b"hello".to_vec());
+    let given: u32 = eval(
+      &mut runtime,
+      &format!(
+        r#"
+          const data = Mach.takeBuffer({id});
+          try {{
+            Mach.takeBuffer({id});
+            throw new Error("taken twice");
+          }} catch (error) {{
+            if (!(error instanceof Deno.errors.NotFound)) throw error;
+          }}
+          return Mach.giveBuffer(data.map((byte) => byte - 32));
+        "#
+      ),
+    )
+    .await
+    .unwrap();
+    let data = runtime.buffers().receive(given).unwrap();
+    assert_eq!(&*data, b"HELLO");
+    assert!(runtime.buffers().receive(given).is_none());
+  }
+
+  #[cfg(unix)]
+  #[tokio::test]
+  async fn pty_resize_reaches_the_child() {
+    let mut runtime = Runtime::new(RunOptions::default()).unwrap();
+    let output: String = eval(
+      &mut runtime,
+      r#"
+        const child = new Deno.Command("sh", {
+          args: ["-c", "read line; stty size"],
+          stdin: "pty",
+          stdout: "pty",
+          stderr: "null",
+          ptySize: { cols: 80, rows: 24 },
+        }).spawn();
+        child.pty.resize({ cols: 100, rows: 40 });
+        const writer = child.pty.writable.getWriter();
+        await writer.write(new TextEncoder().encode("\n"));
+        let output = "";
+        for await (const chunk of child.pty.readable) {
+          output += new TextDecoder().decode(chunk);
+        }
+        await child.status;
+        return output;
+      "#,
+    )
+    .await
+    .unwrap();
+    assert!(output.contains("40 100"), "{output:?}");
+  }
+
+  #[cfg(unix)]
+  #[tokio::test]
+  async fn lines_reads_merged_output_in_order() {
+    let mut runtime = Runtime::new(RunOptions::default()).unwrap();
+    let lines: Vec<String> = eval(
+      &mut runtime,
+      r#"
+        const child = new Deno.Command("sh", {
+          args: ["-c", "echo one; echo two >&2; printf 'three\\r\\nfour'"],
+          mergeOutput: true,
+        }).spawn();
+        const lines = [];
+        for await (const line of child.lines()) {
+          lines.push(line);
+        }
+        await child.status;
+        return lines;
+      "#,
+    )
+    .await
+    .unwrap();
+    assert_eq!(lines, ["one", "two", "three", "four"]);
+  }
+
+  #[cfg(unix)]
+  #[tokio::test]
+  async fn sync_timeout_terminates_grandchildren() {
+    let mut runtime = Runtime::new(RunOptions::default()).unwrap();
+    // Would hang reading stdout if the backgrounded `sleep` outlived `sh`.
+    let (timed_out, signal): (bool, Option<String>) = eval(
+      &mut runtime,
+      r#"
+        const { timedOut, signal } = new Deno.Command("sh", {
+          args: ["-c", "sleep 30 & sleep 30"],
+          timeout: 200,
+        }).outputSync();
+        return [timedOut, signal];
+      "#,
+    )
+    .await
+    .unwrap();
+    assert!(timed_out);
+    assert_eq!(signal.as_deref(), Some("SIGTERM"));
+  }
+
+  #[cfg(unix)]
+  #[tokio::test]
+  async fn abort_sends_sigterm_then_sigkill() {
+    let mut runtime = Runtime::new(RunOptions::default()).unwrap();
+    let (timed_out, signal): (bool, Option<String>) = eval(
+      &mut runtime,
+      r#"
+        const controller = new AbortController();
+        const child = new Deno.Command("sh", {
+          args: ["-c", "trap '' TERM; echo ready; exec sleep 30"],
+          signal: controller.signal,
+          killDelay: 100,
+          stderr: "null",
+        }).spawn();
+        const reader = child.stdout.getReader();
+        await reader.read();
+        controller.abort();
+        const { timedOut, signal } = await child.status;
+        return [timedOut, signal];
+      "#,
+    )
+    .await
+    .unwrap();
+    assert!(!timed_out);
+    assert_eq!(signal.as_deref(), Some("SIGKILL"));
+  }
+
+  #[tokio::test]
+  async fn profile_without_deprecated_run_rejects_only_deno_run() {
+    let mut runtime = Runtime::new(RunOptions {
+      bootstrap_profile: BootstrapProfile {
+        deprecated_run: false,
+        ..Default::default()
+      },
+      ..Default::default()
+    })
+    .unwrap();
+    let names: Vec<String> = eval(
+      &mut runtime,
+      r#"
+        const errorName = (fn) => {
+          try {
+            fn();
+            return "ok";
+          } catch (error) {
+            return error.name;
+          }
+        };
+        return [
+          errorName(() => Deno.run({ cmd: ["true"] })),
+          errorName(() => Deno.kill(Deno.pid, "SIGCONT")),
+        ];
+      "#,
+    )
+    .await
+    .unwrap();
+    assert_eq!(names, ["NotSupported", "ok"]);
+  }
+}
@@ -0,0 +1,147 @@
+use std::fmt;
+use std::rc::Rc;
+
+use deno_core::error::AnyError;
+use deno_core::op2;
+use deno_core::v8;
+use deno_core::OpState;
+use deno_runtime::worker::ExitCode;
+
+use crate::limits::LimitExceeded;
+
+/// Host callback for `Deno.exit()`, called with the exit code in place of
+/// exiting the process.
+#[derive(Clone)]
+pub struct ExitHandler(pub Rc<dyn Fn(i32)>);
+
+deno_core::extension!(
+  deno_host_exit,
+  options = {
+    handler: ExitHandler,
+  },
+  state = |state, options| {
+    state.put::<ExitHandler>(options.handler);
+  },
+  middleware = |op| match op.name {
+    "op_exit" => op.with_implementation_from(&op_host_exit::DECL),
+    _ => op,
+  },
+);
+
+/// Replaces `op_exit`: reports the exit code to the `ExitHandler` and
+/// terminates the isolate so no further JS runs.
+#[op2]
+fn op_host_exit(scope: &mut v8::HandleScope, state: &mut OpState) {
+  let code = state.borrow::<ExitCode>().get();
+  let handler = state.borrow::<ExitHandler>().clone();
+  (handler.0)(code);
+  scope.terminate_execution();
+}
+
+/// How a script run ended.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ExitOutcome {
+  /// The main module and the event loop ran to completion. `code` is
+  /// whatever `Deno.exitCode` was set to, `0` by default.
+  Completed { code: i32 },
+  /// The script called `Deno.exit(code)`.
+  Exited { code: i32 },
+  /// An exception was not caught, formatted with `format_js_error`.
+  UncaughtException(String),
+  /// Execution was terminated, either because a resource limit was
+  /// exceeded or because the host terminated the isolate.
+  Terminated(Option<LimitExceeded>),
+}
+
+impl ExitOutcome {
+  /// The code a process running this script alone would exit with.
+  pub fn exit_code(&self) -> i32 {
+    match self {
+      ExitOutcome::Completed { code } | ExitOutcome::Exited { code } => *code,
+      ExitOutcome::UncaughtException(_) | ExitOutcome::Terminated(_) => 1,
+    }
+  }
+}
+
+/// A failure of the host rather than of the script.
+#[derive(Debug)]
+pub enum RunError {
+  /// The runtime could not be created, e.g. because the snapshot does not
+  /// match the runtime's extensions.
+  Startup(AnyError),
+  /// The main module could not be loaded.
+  Load(AnyError),
+  /// The runtime failed for a reason other than a JS exception.
+  Runtime(AnyError),
+}
+
+impl fmt::Display for RunError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      RunError::Startup(err) => write!(f, "Failed to start runtime: {err}"),
+      RunError::Load(err) => write!(f, "Failed to load main module: {err}"),
+      RunError::Runtime(err) => write!(f, "{err}"),
+    }
+  }
+}
+
+impl std::error::Error for RunError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      RunError::Startup(err) | RunError::Load(err) | RunError::Runtime(err) => {
+        Some(err.as_ref())
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::embed::Runtime;
+  use crate::testing::eval;
+  use crate::RunOptions;
+
+  async fn execute(code: &str) -> (Runtime, ExitOutcome) {
+    let mut runtime = Runtime::new(RunOptions::default()).unwrap();
+    let outcome = runtime.execute_main_module(code.to_string()).await.unwrap();
+    (runtime, outcome)
+  }
+
+  #[tokio::test]
+  async fn exit_is_reported_instead_of_exiting() {
+    let (mut runtime, outcome) = execute(
+      r#"
+        globalThis.reached = false;
+        Deno.exit(3);
+        globalThis.reached = true;
+      "#,
+    )
+    .await;
+    assert_eq!(outcome, ExitOutcome::Exited { code: 3 });
+    assert_eq!(outcome.exit_code(), 3);
+
+    // The termination is cancelled once reported.
+    let reached: bool = eval(&mut runtime, "return globalThis.reached;")
+      .await
+      .unwrap();
+    assert!(!reached);
+  }
+
+  #[tokio::test]
+  async fn completion_reports_exit_code() {
+    let (_, outcome) = execute("Deno.exitCode = 2;").await;
+    assert_eq!(outcome, ExitOutcome::Completed { code: 2 });
+  }
+
+  #[tokio::test]
+  async fn uncaught_exception_is_formatted() {
+    let (_, outcome) = execute("throw new Error('boom');").await;
+    match outcome {
+      ExitOutcome::UncaughtException(message) => {
+        assert!(message.contains("boom"), "{message}")
+      }
+      outcome => panic!("unexpected outcome {outcome:?}"),
+    }
+  }
+}
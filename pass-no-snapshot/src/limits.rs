@@ -0,0 +1,432 @@
+use std::fmt;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use std::time::Instant;
+
+use deno_core::parking_lot::Mutex;
+use deno_core::v8;
+use deno_core::JsRuntime;
+
+/// How often the watchdog thread checks the CPU and wall-clock budgets.
+const WATCHDOG_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Resource limits applied to a single isolate.
+///
+/// Every limit is optional; `WorkerLimits::default()` applies none of them.
+#[derive(Clone, Debug, Default)]
+pub struct WorkerLimits {
+  /// Initial size of the V8 heap in bytes.
+  pub initial_heap_size: Option<usize>,
+  /// Maximum size of the V8 heap in bytes. When the isolate gets close to
+  /// this limit it is terminated instead of aborting the whole process.
+  pub max_heap_size: Option<usize>,
+  /// CPU time the isolate thread may consume.
+  pub cpu_time: Option<Duration>,
+  /// Wall-clock time the isolate may run for: a web worker for its whole
+  /// life, a `Runtime` for each execution, see
+  /// `LimitsWatchdog::time_execution`.
+  pub timeout: Option<Duration>,
+}
+
+/// The limit that caused an isolate to be terminated.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LimitExceeded {
+  HeapSize { limit: usize },
+  CpuTime { limit: Duration },
+  Timeout { limit: Duration },
+}
+
+impl fmt::Display for LimitExceeded {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      LimitExceeded::HeapSize { limit } => {
+        write!(f, "Worker exceeded its heap limit of {limit} bytes")
+      }
+      LimitExceeded::CpuTime { limit } => {
+        write!(f, "Worker exceeded its CPU time budget of {limit:?}")
+      }
+      LimitExceeded::Timeout { limit } => {
+        write!(f, "Worker exceeded its wall-clock timeout of {limit:?}")
+      }
+    }
+  }
+}
+
+impl std::error::Error for LimitExceeded {}
+
+impl WorkerLimits {
+  /// V8 create params for the heap limits, to be passed as `create_params`
+  /// in `WorkerOptions`, `WebWorkerOptions` or `RuntimeOptions`.
+  pub fn create_params(&self) -> Option<v8::CreateParams> {
+    let max_heap_size = self.max_heap_size?;
+    let initial_heap_size = self.initial_heap_size.unwrap_or(0);
+    Some(
+      v8::CreateParams::default().heap_limits(initial_heap_size, max_heap_size),
+    )
+  }
+
+  /// Starts enforcing the limits on `js_runtime`.
+  ///
+  /// Must be called on the thread that runs the isolate, because the CPU
+  /// budget is measured against the calling thread. The limits stay in force
+  /// until the returned watchdog is dropped.
+  pub fn install(&self, js_runtime: &mut JsRuntime) -> LimitsWatchdog {
+    self.install_with(js_runtime, |_| {})
+  }
+
+  /// Like `install`, calling `on_violation` with the first limit exceeded
+  /// right before the isolate is terminated. It may be called from the
+  /// watchdog thread or from within V8 on the isolate thread.
+  pub fn install_with(
+    &self,
+    js_runtime: &mut JsRuntime,
+    on_violation: impl Fn(&LimitExceeded) + Send + Sync + 'static,
+  ) -> LimitsWatchdog {
+    let violation = Arc::new(Violation {
+      exceeded: Mutex::new(None),
+      on_violation: Box::new(on_violation),
+    });
+    let isolate_handle = js_runtime.v8_isolate().thread_safe_handle();
+    let timeout_state = Arc::new(Mutex::new(TimeoutState {
+      started: Some(Instant::now()),
+      executions: 0,
+    }));
+
+    if let Some(limit) = self.max_heap_size {
+      let violation = violation.clone();
+      let isolate_handle = isolate_handle.clone();
+      js_runtime.add_near_heap_limit_callback(move |current_limit, _| {
+        report(
+          &violation,
+          &isolate_handle,
+          LimitExceeded::HeapSize { limit },
+        );
+        // Give V8 enough headroom to unwind the terminated execution.
+        current_limit * 2
+      });
+    }
+
+    if self.cpu_time.is_none() && self.timeout.is_none() {
+      return LimitsWatchdog {
+        violation,
+        timeout_state,
+        cancel: None,
+        thread: None,
+      };
+    }
+
+    let cpu_clock = self.cpu_time.and_then(|_| ThreadCpuClock::current());
+    let cpu_time = self.cpu_time;
+    let timeout = self.timeout;
+    let (cancel, cancel_rx) = mpsc::channel::<()>();
+    let thread = {
+      let violation = violation.clone();
+      let timeout_state = timeout_state.clone();
+      std::thread::Builder::new()
+        .name("worker-limits".to_string())
+        .spawn(move || loop {
+          match cancel_rx.recv_timeout(WATCHDOG_INTERVAL) {
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            _ => return,
+          }
+          let started = timeout_state.lock().started;
+          if let (Some(limit), Some(started)) = (timeout, started) {
+            if started.elapsed() >= limit {
+              report(
+                &violation,
+                &isolate_handle,
+                LimitExceeded::Timeout { limit },
+              );
+              return;
+            }
+          }
+          if let (Some(limit), Some(clock)) = (cpu_time, &cpu_clock) {
+            if clock.elapsed() >= limit {
+              report(
+                &violation,
+                &isolate_handle,
+                LimitExceeded::CpuTime { limit },
+              );
+              return;
+            }
+          }
+        })
+        .unwrap()
+    };
+
+    LimitsWatchdog {
+      violation,
+      timeout_state,
+      cancel: Some(cancel),
+      thread: Some(thread),
+    }
+  }
+}
+
+struct Violation {
+  exceeded: Mutex<Option<LimitExceeded>>,
+  on_violation: Box<dyn Fn(&LimitExceeded) + Send + Sync>,
+}
+
+fn report(
+  violation: &Violation,
+  isolate_handle: &v8::IsolateHandle,
+  exceeded: LimitExceeded,
+) {
+  let mut recorded = violation.exceeded.lock();
+  if recorded.is_none() {
+    (violation.on_violation)(&exceeded);
+    *recorded = Some(exceeded);
+  }
+  isolate_handle.terminate_execution();
+}
+
+/// Enforces the CPU and wall-clock budgets of one isolate from a background
+/// thread. Dropping it stops enforcement.
+pub struct LimitsWatchdog {
+  violation: Arc<Violation>,
+  timeout_state: Arc<Mutex<TimeoutState>>,
+  cancel: Option<mpsc::Sender<()>>,
+  thread: Option<JoinHandle<()>>,
+}
+
+impl LimitsWatchdog {
+  /// The limit that terminated the isolate, if any.
+  pub fn violation(&self) -> Option<LimitExceeded> {
+    self.violation.exceeded.lock().clone()
+  }
+
+  /// Restarts the wall-clock timeout for an execution of the isolate and
+  /// pauses it once the returned guard is dropped, so that the time the
+  /// isolate is idle between executions doesn't count. Until the first call
+  /// the timeout runs from `install`. Executions may nest, the timeout then
+  /// covers the outermost one.
+  pub fn time_execution(&self) -> ExecutionTimeout {
+    let mut state = self.timeout_state.lock();
+    if state.executions == 0 {
+      state.started = Some(Instant::now());
+    }
+    state.executions += 1;
+    ExecutionTimeout(self.timeout_state.clone())
+  }
+}
+
+struct TimeoutState {
+  /// When the wall-clock timeout started, or `None` while it is paused.
+  started: Option<Instant>,
+  executions: usize,
+}
+
+/// Pauses the wall-clock timeout when dropped, see
+/// `LimitsWatchdog::time_execution`.
+pub struct ExecutionTimeout(Arc<Mutex<TimeoutState>>);
+
+impl Drop for ExecutionTimeout {
+  fn drop(&mut self) {
+    let mut state = self.0.lock();
+    state.executions -= 1;
+    if state.executions == 0 {
+      state.started = None;
+    }
+  }
+}
+
+impl Drop for LimitsWatchdog {
+  fn drop(&mut self) {
+    if let Some(cancel) = self.cancel.take() {
+      let _ = cancel.send(());
+    }
+    if let Some(thread) = self.thread.take() {
+      let _ = thread.join();
+    }
+  }
+}
+
+/// CPU time the thread that created it has used since, readable from any
+/// thread.
+struct ThreadCpuClock {
+  clock_id: libc::clockid_t,
+  start: Duration,
+}
+
+impl ThreadCpuClock {
+  #[cfg(target_os = "linux")]
+  fn current() -> Option<Self> {
+    let mut clock_id: libc::clockid_t = 0;
+    // SAFETY: libc call with a valid out pointer
+    let ret = unsafe {
+      libc::pthread_getcpuclockid(libc::pthread_self(), &mut clock_id)
+    };
+    if ret != 0 {
+      return None;
+    }
+    let start = read_clock(clock_id)?;
+    Some(ThreadCpuClock { clock_id, start })
+  }
+
+  #[cfg(not(target_os = "linux"))]
+  fn current() -> Option<Self> {
+    None
+  }
+
+  fn elapsed(&self) -> Duration {
+    read_clock(self.clock_id)
+      .map(|now| now.saturating_sub(self.start))
+      .unwrap_or_default()
+  }
+}
+
+fn read_clock(clock_id: libc::clockid_t) -> Option<Duration> {
+  let mut time = libc::timespec {
+    tv_sec: 0,
+    tv_nsec: 0,
+  };
+  // SAFETY: libc call with a valid out pointer
+  if unsafe { libc::clock_gettime(clock_id, &mut time) } != 0 {
+    return None;
+  }
+  Some(Duration::new(time.tv_sec as u64, time.tv_nsec as u32))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::embed::Runtime;
+  use crate::exit::ExitOutcome;
+  use crate::testing::eval;
+  use crate::RunOptions;
+
+  async fn run_with_limits(limits: WorkerLimits, code: &str) -> ExitOutcome {
+    let mut runtime = Runtime::new(RunOptions {
+      limits,
+      ..Default::default()
+    })
+    .unwrap();
+    runtime.execute_main_module(code.to_string()).await.unwrap()
+  }
+
+  #[tokio::test]
+  async fn heap_limit_terminates_execution() {
+    let limit = 32 * 1024 * 1024;
+    let outcome = run_with_limits(
+      WorkerLimits {
+        max_heap_size: Some(limit),
+        ..Default::default()
+      },
+      "const chunks = []; while (true) chunks.push(new Array(1e5).fill(1));",
+    )
+    .await;
+    assert_eq!(
+      outcome,
+      ExitOutcome::Terminated(Some(LimitExceeded::HeapSize { limit }))
+    );
+  }
+
+  #[tokio::test]
+  async fn cpu_time_limit_terminates_execution() {
+    let limit = Duration::from_millis(500);
+    let outcome = run_with_limits(
+      WorkerLimits {
+        cpu_time: Some(limit),
+        ..Default::default()
+      },
+      "while (true) {}",
+    )
+    .await;
+    assert_eq!(
+      outcome,
+      ExitOutcome::Terminated(Some(LimitExceeded::CpuTime { limit }))
+    );
+  }
+
+  #[tokio::test]
+  async fn timeout_terminates_execution() {
+    let limit = Duration::from_millis(500);
+    let outcome = run_with_limits(
+      WorkerLimits {
+        timeout: Some(limit),
+        ..Default::default()
+      },
+      "while (true) {}",
+    )
+    .await;
+    assert_eq!(
+      outcome,
+      ExitOutcome::Terminated(Some(LimitExceeded::Timeout { limit }))
+    );
+  }
+
+  #[tokio::test]
+  async fn timeout_applies_to_each_execution() {
+    let limit = Duration::from_millis(500);
+    let mut runtime = Runtime::new(RunOptions {
+      limits: WorkerLimits {
+        timeout: Some(limit),
+        ..Default::default()
+      },
+      ..Default::default()
+    })
+    .unwrap();
+    // Neither the time the runtime is idle nor earlier executions count.
+    tokio::time::sleep(limit).await;
+    for _ in 0..3 {
+      let () = eval(
+        &mut runtime,
+        "const end = Date.now() + 300; while (Date.now() < end) {}",
+      )
+      .await
+      .unwrap();
+    }
+    assert_eq!(runtime.terminated(), None);
+  }
+
+  #[cfg(target_os = "linux")]
+  #[test]
+  fn thread_cpu_clock_starts_when_created() {
+    let busy_until = Instant::now() + Duration::from_millis(300);
+    while Instant::now() < busy_until {}
+    let clock = ThreadCpuClock::current().unwrap();
+    assert!(clock.elapsed() < Duration::from_millis(100));
+  }
+
+  #[tokio::test]
+  async fn web_worker_reports_exceeded_limit_to_parent() {
+    let limit = 32 * 1024 * 1024;
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("allocate.js");
+    std::fs::write(
+      &path,
+      "const chunks = []; while (true) chunks.push(new Array(1e5).fill(1));",
+    )
+    .unwrap();
+    let mut runtime = Runtime::new(RunOptions {
+      limits: WorkerLimits {
+        max_heap_size: Some(limit),
+        ..Default::default()
+      },
+      ..Default::default()
+    })
+    .unwrap();
+    let message: String = eval(
+      &mut runtime,
+      &format!(
+        r#"
+          const worker = new Worker({:?}, {{ type: "module" }});
+          return await new Promise((resolve) => {{
+            worker.onerror = (event) => {{
+              event.preventDefault();
+              resolve(event.message);
+            }};
+          }});
+        "#,
+        deno_core::url::Url::from_file_path(&path).unwrap().as_str()
+      ),
+    )
+    .await
+    .unwrap();
+    let expected = LimitExceeded::HeapSize { limit }.to_string();
+    assert!(message.contains(&expected), "{message}");
+  }
+}
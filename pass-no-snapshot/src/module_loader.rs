@@ -0,0 +1,49 @@
+use deno_core::error::AnyError;
+use deno_core::FsModuleLoader;
+use deno_core::ModuleLoadResponse;
+use deno_core::ModuleLoader;
+use deno_core::ModuleSpecifier;
+use deno_core::RequestedModuleType;
+use deno_core::ResolutionKind;
+
+use crate::permissions::ImportPermission;
+
+/// Loads modules from the file system like `FsModuleLoader`, after checking
+/// every resolved specifier against the `import` permission.
+pub struct HostModuleLoader {
+  import: ImportPermission,
+}
+
+impl HostModuleLoader {
+  pub fn new(import: ImportPermission) -> Self {
+    Self { import }
+  }
+}
+
+impl ModuleLoader for HostModuleLoader {
+  fn resolve(
+    &self,
+    specifier: &str,
+    referrer: &str,
+    kind: ResolutionKind,
+  ) -> Result<ModuleSpecifier, AnyError> {
+    let specifier = FsModuleLoader.resolve(specifier, referrer, kind)?;
+    self.import.check(&specifier)?;
+    Ok(specifier)
+  }
+
+  fn load(
+    &self,
+    module_specifier: &ModuleSpecifier,
+    maybe_referrer: Option<&ModuleSpecifier>,
+    is_dynamic: bool,
+    requested_module_type: RequestedModuleType,
+  ) -> ModuleLoadResponse {
+    FsModuleLoader.load(
+      module_specifier,
+      maybe_referrer,
+      is_dynamic,
+      requested_module_type,
+    )
+  }
+}
@@ -0,0 +1,159 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use deno_core::error::custom_error;
+use deno_core::error::AnyError;
+use deno_core::op2;
+use deno_core::Extension;
+use deno_core::OpDecl;
+use deno_runtime::ops;
+
+#[cfg(any(feature = "ffi", feature = "napi"))]
+use crate::permissions::Permissions;
+
+/// Replaces denied ops with a stub that throws `PermissionDenied`.
+///
+/// Ops are denied by name, or in bulk by passing the extension that
+/// declares them. Filtering happens when the runtime is constructed, so a
+/// denied op is never reachable from JS regardless of what the permission
+/// checks inside it would have allowed.
+///
+/// ```ignore
+/// let filter = OpFilter::default()
+///   .deny_extension(ops::process::deno_process::init_ops())
+///   .deny_op("op_kill");
+/// ```
+#[derive(Clone, Default)]
+pub struct OpFilter {
+  denied: HashSet<&'static str>,
+}
+
+impl OpFilter {
+  /// Denies every op of `ext`. Only the op declarations are inspected, the
+  /// extension itself is dropped.
+  pub fn deny_extension(mut self, ext: Extension) -> Self {
+    self.denied.extend(ext.ops.iter().map(|op| op.name));
+    self
+  }
+
+  /// Denies subprocesses and native code: `deno_process` and the ops the
+  /// `process` host extension adds to it, and `deno_ffi` and `deno_napi`
+  /// when their features are enabled.
+  pub fn sandbox() -> Self {
+    let filter = Self::default()
+      .deny_extension(ops::process::deno_process::init_ops())
+      .deny_extension(host_extensions::process::extension().for_runtime());
+    #[cfg(feature = "ffi")]
+    let filter =
+      filter.deny_extension(deno_ffi::deno_ffi::init_ops::<Permissions>());
+    #[cfg(feature = "napi")]
+    let filter =
+      filter.deny_extension(deno_napi::deno_napi::init_ops::<Permissions>());
+    filter
+  }
+
+  pub fn deny_op(mut self, name: &'static str) -> Self {
+    self.denied.insert(name);
+    self
+  }
+
+  pub fn is_denied(&self, name: &str) -> bool {
+    self.denied.contains(name)
+  }
+
+  /// An extension without ops whose middleware stubs out the denied ops of
+  /// every other extension in the runtime. Append it to the extension list
+  /// of a `JsRuntime`, `MainWorker` or `WebWorker`, after any extension whose
+  /// middleware replaces ops, such as `host_extensions::op_overrides()`.
+  pub fn extension(&self) -> Extension {
+    let denied = Arc::new(self.denied.clone());
+    Extension {
+      name: "deno_op_filter",
+      middleware_fn: Some(Box::new(move |op: OpDecl| {
+        if denied.contains(op.name) {
+          op.with_implementation_from(&op_denied::DECL)
+        } else {
+          op
+        }
+      })),
+      ..Default::default()
+    }
+  }
+}
+
+#[op2]
+fn op_denied() -> Result<(), AnyError> {
+  Err(custom_error(
+    "PermissionDenied",
+    "This operation is not available in this runtime",
+  ))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::embed::Runtime;
+  use crate::testing::eval;
+  use crate::RunOptions;
+
+  #[test]
+  fn deny_extension_denies_its_ops() {
+    let filter = OpFilter::default()
+      .deny_extension(ops::process::deno_process::init_ops())
+      .deny_op("op_fs_cwd");
+    assert!(filter.is_denied("op_spawn_child"));
+    assert!(filter.is_denied("op_fs_cwd"));
+    assert!(!filter.is_denied("op_fs_stat_sync"));
+  }
+
+  #[tokio::test]
+  async fn denied_op_throws_permission_denied() {
+    let mut runtime = Runtime::new(RunOptions {
+      op_filter: OpFilter::default().deny_op("op_fs_cwd"),
+      ..Default::default()
+    })
+    .unwrap();
+    let names: Vec<String> = eval(
+      &mut runtime,
+      r#"
+        const errorName = (fn) => {
+          try {
+            fn();
+            return "ok";
+          } catch (error) {
+            return error.name;
+          }
+        };
+        return [errorName(() => Deno.cwd()), errorName(() => Deno.pid)];
+      "#,
+    )
+    .await
+    .unwrap();
+    assert_eq!(names, ["PermissionDenied", "ok"]);
+  }
+
+  #[tokio::test]
+  async fn sandbox_denies_overridden_process_ops() {
+    let filter = OpFilter::sandbox();
+    assert!(filter.is_denied("op_pty_resize"));
+    let mut runtime = Runtime::new(RunOptions {
+      op_filter: filter,
+      ..Default::default()
+    })
+    .unwrap();
+    let name: String = eval(
+      &mut runtime,
+      r#"
+        try {
+          new Deno.Command("true").outputSync();
+          return "ok";
+        } catch (error) {
+          return error.name;
+        }
+      "#,
+    )
+    .await
+    .unwrap();
+    assert_eq!(name, "PermissionDenied");
+  }
+}
@@ -0,0 +1,275 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::time::Duration;
+use std::time::Instant;
+
+use deno_core::OpCtx;
+use deno_core::OpDecl;
+use deno_core::OpMetricsEvent;
+use deno_core::OpMetricsFactoryFn;
+use deno_core::OpMetricsFn;
+use deno_core::OpMetricsSource;
+
+/// Op tracing for the main runtime.
+///
+/// Web workers are not traced and are bootstrapped without op summary
+/// metrics: `deno_runtime` builds their `JsRuntime` itself and offers no
+/// hook for an op metrics factory.
+#[derive(Clone, Debug, Default)]
+pub struct OpTraceOptions {
+  /// Log every dispatch and completion of the listed ops to stderr. An empty
+  /// list traces all ops.
+  pub strace_ops: Option<Vec<String>>,
+  /// Collect per-op call counts and timings for `OpTracer::print_summary`.
+  pub enable_op_summary_metrics: bool,
+}
+
+impl OpTraceOptions {
+  pub fn is_enabled(&self) -> bool {
+    self.strace_ops.is_some() || self.enable_op_summary_metrics
+  }
+
+  fn traces(&self, name: &str) -> bool {
+    match &self.strace_ops {
+      Some(ops) => ops.is_empty() || ops.iter().any(|op| op == name),
+      None => false,
+    }
+  }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct OpSummary {
+  pub name: &'static str,
+  pub sync_calls: u64,
+  pub async_calls: u64,
+  pub errors: u64,
+  pub total_time: Duration,
+}
+
+/// Records op dispatches of one runtime through deno_core's op metrics
+/// hooks.
+///
+/// Calls are timed from dispatch to completion. Sync calls are matched
+/// innermost-first; async calls of the same op are matched in dispatch
+/// order, so their individual durations are approximate when calls overlap
+/// and complete out of order. Totals are unaffected.
+pub struct OpTracer {
+  options: OpTraceOptions,
+  started: Instant,
+  summary: RefCell<HashMap<&'static str, OpSummary>>,
+  pending: RefCell<HashMap<&'static str, VecDeque<Instant>>>,
+}
+
+impl OpTracer {
+  pub fn new(options: OpTraceOptions) -> Rc<Self> {
+    Rc::new(Self {
+      options,
+      started: Instant::now(),
+      summary: Default::default(),
+      pending: Default::default(),
+    })
+  }
+
+  /// Factory for `RuntimeOptions::op_metrics_factory_fn`. Returns `None`
+  /// when tracing is disabled so untraced runtimes pay nothing.
+  pub fn op_metrics_factory_fn(self: &Rc<Self>) -> Option<OpMetricsFactoryFn> {
+    if !self.options.is_enabled() {
+      return None;
+    }
+    let tracer = self.clone();
+    Some(Box::new(move |_: usize, _: usize, decl: &OpDecl| {
+      let name = decl.name;
+      let strace = tracer.options.traces(name);
+      if !strace && !tracer.options.enable_op_summary_metrics {
+        return None;
+      }
+      let tracer = tracer.clone();
+      let metrics_fn: OpMetricsFn = Rc::new(
+        move |_: &OpCtx, event: OpMetricsEvent, source: OpMetricsSource| {
+          tracer.record(name, strace, event, source);
+        },
+      );
+      Some(metrics_fn)
+    }))
+  }
+
+  fn record(
+    &self,
+    name: &'static str,
+    strace: bool,
+    event: OpMetricsEvent,
+    source: OpMetricsSource,
+  ) {
+    let now = Instant::now();
+    let is_async = matches!(source, OpMetricsSource::Async);
+    let duration = self.call_duration(name, &event, now);
+
+    if self.options.enable_op_summary_metrics {
+      let mut summary = self.summary.borrow_mut();
+      let entry = summary.entry(name).or_insert_with(|| OpSummary {
+        name,
+        ..Default::default()
+      });
+      match event {
+        OpMetricsEvent::Dispatched if is_async => entry.async_calls += 1,
+        OpMetricsEvent::Dispatched => entry.sync_calls += 1,
+        OpMetricsEvent::Error | OpMetricsEvent::ErrorAsync => entry.errors += 1,
+        _ => {}
+      }
+      if let Some(duration) = duration {
+        entry.total_time += duration;
+      }
+    }
+
+    if strace {
+      let kind = if is_async { "async" } else { "sync" };
+      let elapsed = (now - self.started).as_secs_f64() * 1000.0;
+      match duration {
+        Some(duration) => eprintln!(
+          "[{elapsed: >10.3}] {name:32} {kind:5} {event:?} ({:.3}ms)",
+          duration.as_secs_f64() * 1000.0
+        ),
+        None => eprintln!("[{elapsed: >10.3}] {name:32} {kind:5} {event:?}"),
+      }
+    }
+  }
+
+  /// Records a dispatch of `name` at `now`, or returns how long the call
+  /// that completed at `now` took.
+  fn call_duration(
+    &self,
+    name: &'static str,
+    event: &OpMetricsEvent,
+    now: Instant,
+  ) -> Option<Duration> {
+    let mut pending = self.pending.borrow_mut();
+    let started = match event {
+      OpMetricsEvent::Dispatched => {
+        pending.entry(name).or_default().push_back(now);
+        return None;
+      }
+      OpMetricsEvent::Completed | OpMetricsEvent::Error => {
+        pending.get_mut(name).and_then(|p| p.pop_back())
+      }
+      OpMetricsEvent::CompletedAsync | OpMetricsEvent::ErrorAsync => {
+        pending.get_mut(name).and_then(|p| p.pop_front())
+      }
+    };
+    started.map(|started| now - started)
+  }
+
+  /// Per-op totals, slowest op first.
+  pub fn summary(&self) -> Vec<OpSummary> {
+    let mut summary: Vec<_> = self.summary.borrow().values().cloned().collect();
+    summary.sort_by(|a, b| b.total_time.cmp(&a.total_time));
+    summary
+  }
+
+  /// Prints `summary()` as a table to stderr.
+  pub fn print_summary(&self) {
+    eprint!("{}", self.format_summary());
+  }
+
+  /// `summary()` as a table, empty if no op was recorded.
+  fn format_summary(&self) -> String {
+    let summary = self.summary();
+    if summary.is_empty() {
+      return String::new();
+    }
+    let mut table = format!(
+      "{:32} {:>10} {:>10} {:>8} {:>12}\n",
+      "op", "sync", "async", "errors", "total (ms)"
+    );
+    for op in summary {
+      table += &format!(
+        "{:32} {:>10} {:>10} {:>8} {:>12.3}\n",
+        op.name,
+        op.sync_calls,
+        op.async_calls,
+        op.errors,
+        op.total_time.as_secs_f64() * 1000.0
+      );
+    }
+    table
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn tracer() -> Rc<OpTracer> {
+    OpTracer::new(OpTraceOptions {
+      strace_ops: None,
+      enable_op_summary_metrics: true,
+    })
+  }
+
+  fn ms(ms: u64) -> Duration {
+    Duration::from_millis(ms)
+  }
+
+  #[test]
+  fn sync_calls_complete_innermost_first() {
+    let tracer = tracer();
+    let start = Instant::now();
+    let dispatched = OpMetricsEvent::Dispatched;
+    let completed = OpMetricsEvent::Completed;
+    tracer.call_duration("op_a", &dispatched, start);
+    tracer.call_duration("op_a", &dispatched, start + ms(1));
+    let inner = tracer.call_duration("op_a", &completed, start + ms(3));
+    let outer = tracer.call_duration("op_a", &completed, start + ms(10));
+    assert_eq!((inner, outer), (Some(ms(2)), Some(ms(10))));
+  }
+
+  #[test]
+  fn async_calls_complete_in_dispatch_order() {
+    let tracer = tracer();
+    let start = Instant::now();
+    let dispatched = OpMetricsEvent::Dispatched;
+    let completed = OpMetricsEvent::CompletedAsync;
+    tracer.call_duration("op_a", &dispatched, start);
+    tracer.call_duration("op_a", &dispatched, start + ms(1));
+    let first = tracer.call_duration("op_a", &completed, start + ms(3));
+    let second = tracer.call_duration("op_a", &completed, start + ms(10));
+    assert_eq!((first, second), (Some(ms(3)), Some(ms(9))));
+    assert_eq!(tracer.call_duration("op_a", &completed, start), None);
+  }
+
+  #[test]
+  fn summary_counts_calls_and_errors_per_op() {
+    let tracer = tracer();
+    let (sync, fast) = (OpMetricsSource::Slow, OpMetricsSource::Fast);
+    tracer.record("op_a", false, OpMetricsEvent::Dispatched, sync);
+    tracer.record("op_a", false, OpMetricsEvent::Error, sync);
+    tracer.record("op_a", false, OpMetricsEvent::Dispatched, fast);
+    tracer.record("op_a", false, OpMetricsEvent::Completed, fast);
+    let source = OpMetricsSource::Async;
+    tracer.record("op_b", false, OpMetricsEvent::Dispatched, source);
+    std::thread::sleep(ms(5));
+    tracer.record("op_b", false, OpMetricsEvent::CompletedAsync, source);
+
+    let summary = tracer.summary();
+    let counts = summary
+      .iter()
+      .map(|op| (op.name, op.sync_calls, op.async_calls, op.errors))
+      .collect::<Vec<_>>();
+    assert_eq!(counts, [("op_b", 0, 1, 0), ("op_a", 2, 0, 1)]);
+    assert!(summary[0].total_time >= ms(5));
+
+    let table = tracer.format_summary();
+    let lines = table.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].starts_with("op "), "{table}");
+    assert!(lines[0].ends_with("total (ms)"), "{table}");
+    let row = lines[2].split_whitespace().collect::<Vec<_>>();
+    assert_eq!(row[..4], ["op_a", "2", "0", "1"]);
+  }
+
+  #[test]
+  fn summary_is_empty_without_calls() {
+    assert_eq!(tracer().format_summary(), "");
+  }
+}
@@ -1,17 +1,590 @@
+use std::cell::RefCell;
+use std::net::IpAddr;
 use std::path::Path;
+use std::rc::Rc;
 
+use deno_core::error::custom_error;
+use deno_core::error::uri_error;
 use deno_core::error::AnyError;
+use deno_core::op2;
+use deno_core::url::Url;
+use deno_core::OpState;
+use deno_runtime::permissions::parse_sys_kind;
+use deno_runtime::permissions::PermissionState;
+use deno_runtime::permissions::Permissions as ContainerPermissions;
+use deno_runtime::permissions::PermissionsContainer;
+use serde::Deserialize;
+use serde::Serialize;
 
-#[derive(Clone)]
-pub struct Permissions;
+/// A single port or an inclusive range of ports, written as `443` or
+/// `8000-8999`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PortRange {
+  pub start: u16,
+  pub end: u16,
+}
+
+impl PortRange {
+  pub fn parse(s: &str) -> Result<Self, AnyError> {
+    let invalid = || uri_error(format!("Invalid port range: {s}"));
+    let (start, end) = match s.split_once('-') {
+      Some((start, end)) => (start, end),
+      None => (s, s),
+    };
+    let start = start.parse::<u16>().map_err(|_| invalid())?;
+    let end = end.parse::<u16>().map_err(|_| invalid())?;
+    if start > end {
+      return Err(invalid());
+    }
+    Ok(PortRange { start, end })
+  }
+
+  pub fn contains(&self, port: u16) -> bool {
+    self.start <= port && port <= self.end
+  }
+}
+
+/// A net or import permission descriptor: either a hostname or a CIDR
+/// block, optionally restricted to a port range.
+///
+/// Accepted forms are `example.com`, `example.com:8000-8999`,
+/// `10.0.0.0/8`, `10.0.0.0/8:443` and `[fd00::/8]:443`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum NetDescriptor {
+  Host(String, Option<PortRange>),
+  Cidr(IpAddr, u8, Option<PortRange>),
+}
+
+impl NetDescriptor {
+  pub fn parse(s: &str) -> Result<Self, AnyError> {
+    let invalid = || uri_error(format!("Invalid host: {s}"));
+    let (addr, ports) = if let Some(rest) = s.strip_prefix('[') {
+      let (addr, rest) = rest.split_once(']').ok_or_else(invalid)?;
+      match rest {
+        "" => (addr, None),
+        _ => (addr, Some(rest.strip_prefix(':').ok_or_else(invalid)?)),
+      }
+    } else if s.matches(':').count() > 1 {
+      // Bare IPv6 address, ports require the bracketed form.
+      (s, None)
+    } else {
+      match s.split_once(':') {
+        Some((addr, ports)) => (addr, Some(ports)),
+        None => (s, None),
+      }
+    };
+    let ports = ports.map(PortRange::parse).transpose()?;
+
+    if let Some((ip, prefix)) = addr.split_once('/') {
+      let ip = ip.parse::<IpAddr>().map_err(|_| invalid())?;
+      let prefix = prefix.parse::<u8>().map_err(|_| invalid())?;
+      let max_prefix = if ip.is_ipv4() { 32 } else { 128 };
+      if prefix > max_prefix {
+        return Err(invalid());
+      }
+      return Ok(NetDescriptor::Cidr(ip, prefix, ports));
+    }
+
+    if let Ok(ip) = addr.parse::<IpAddr>() {
+      let prefix = if ip.is_ipv4() { 32 } else { 128 };
+      return Ok(NetDescriptor::Cidr(ip, prefix, ports));
+    }
+
+    // Run the hostname through the URL parser so it is normalized the same
+    // way as the hosts we later check against.
+    let url = Url::parse(&format!("http://{addr}/")).map_err(|_| invalid())?;
+    if url.path() != "/" || url.port().is_some() {
+      return Err(invalid());
+    }
+    let hostname = url.host_str().ok_or_else(invalid)?;
+    Ok(NetDescriptor::Host(hostname.to_string(), ports))
+  }
+
+  pub fn ports(&self) -> Option<&PortRange> {
+    match self {
+      NetDescriptor::Host(_, ports) => ports.as_ref(),
+      NetDescriptor::Cidr(_, _, ports) => ports.as_ref(),
+    }
+  }
+
+  /// Returns true if `host` (a hostname or IP literal) and `port` fall
+  /// within this descriptor. A descriptor without a port range matches any
+  /// port; one with a port range never matches an unknown port.
+  pub fn matches(&self, host: &str, port: Option<u16>) -> bool {
+    let port_matches = match (self.ports(), port) {
+      (None, _) => true,
+      (Some(range), Some(port)) => range.contains(port),
+      (Some(_), None) => false,
+    };
+    if !port_matches {
+      return false;
+    }
+
+    match self {
+      NetDescriptor::Host(hostname, _) => hostname == host,
+      NetDescriptor::Cidr(net, prefix, _) => {
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        match host.parse::<IpAddr>() {
+          Ok(ip) => cidr_contains(net, *prefix, &ip),
+          Err(_) => false,
+        }
+      }
+    }
+  }
+}
+
+fn cidr_contains(net: &IpAddr, prefix: u8, ip: &IpAddr) -> bool {
+  match (net, ip) {
+    (IpAddr::V4(net), IpAddr::V4(ip)) => {
+      let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+      u32::from(*net) & mask == u32::from(*ip) & mask
+    }
+    (IpAddr::V6(net), IpAddr::V6(ip)) => {
+      let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+      u128::from(*net) & mask == u128::from(*ip) & mask
+    }
+    _ => false,
+  }
+}
+
+impl PortRange {
+  fn covers(&self, other: &PortRange) -> bool {
+    self.start <= other.start && other.end <= self.end
+  }
+}
+
+impl NetDescriptor {
+  /// Returns true if every host and port matched by `other` is matched by
+  /// this descriptor too.
+  pub fn covers(&self, other: &NetDescriptor) -> bool {
+    let ports_covered = match (self.ports(), other.ports()) {
+      (None, _) => true,
+      (Some(range), Some(other)) => range.covers(other),
+      (Some(_), None) => false,
+    };
+    ports_covered
+      && match (self, other) {
+        (NetDescriptor::Host(host, _), NetDescriptor::Host(other, _)) => {
+          host == other
+        }
+        (
+          NetDescriptor::Cidr(net, prefix, _),
+          NetDescriptor::Cidr(other, other_prefix, _),
+        ) => prefix <= other_prefix && cidr_contains(net, *prefix, other),
+        _ => false,
+      }
+  }
+
+  /// The descriptor in the `host[:port]` form `PermissionsContainer`
+  /// understands, if it can be written that way.
+  fn to_container_host(&self) -> Option<String> {
+    let (host, ports) = match self {
+      NetDescriptor::Host(host, ports) => (host.clone(), ports),
+      NetDescriptor::Cidr(IpAddr::V4(ip), 32, ports) => (ip.to_string(), ports),
+      NetDescriptor::Cidr(IpAddr::V6(ip), 128, ports) => {
+        (format!("[{ip}]"), ports)
+      }
+      NetDescriptor::Cidr(..) => return None,
+    };
+    match ports {
+      None => Some(host),
+      Some(PortRange { start, end }) if start == end => {
+        Some(format!("{host}:{start}"))
+      }
+      Some(_) => None,
+    }
+  }
+}
+
+/// The hosts a `net` or `import` permission is granted for.
+///
+/// The default grants every host. Revoking a descriptor denies it from then
+/// on, even where it overlaps one that was allowed. Nothing is ever
+/// prompted for, so `request` only reports the current state.
+#[derive(Clone, Debug, Default)]
+pub struct NetPermission {
+  allow: Option<Vec<NetDescriptor>>,
+  revoked: Vec<NetDescriptor>,
+}
+
+impl NetPermission {
+  /// Grants only the hosts matched by one of the `allow` descriptors, see
+  /// `NetDescriptor::parse`.
+  pub fn allow(allow: &[&str]) -> Result<Self, AnyError> {
+    let allow = allow
+      .iter()
+      .map(|s| NetDescriptor::parse(s))
+      .collect::<Result<_, _>>()?;
+    Ok(Self {
+      allow: Some(allow),
+      revoked: vec![],
+    })
+  }
+
+  fn check(
+    &self,
+    name: &str,
+    host: &str,
+    port: Option<u16>,
+    api_name: &str,
+  ) -> Result<(), AnyError> {
+    let revoked = self.revoked.iter().any(|desc| desc.matches(host, port));
+    let allowed = match &self.allow {
+      None => true,
+      Some(allow) => allow.iter().any(|desc| desc.matches(host, port)),
+    };
+    if allowed && !revoked {
+      return Ok(());
+    }
+    let display = match port {
+      Some(port) => format!("{host}:{port}"),
+      None => host.to_string(),
+    };
+    Err(custom_error(
+      "PermissionDenied",
+      format!("Requires {name} access to \"{display}\" ({api_name})"),
+    ))
+  }
+
+  fn query(&self, desc: Option<&NetDescriptor>) -> PermissionState {
+    let Some(desc) = desc else {
+      return match &self.allow {
+        None if self.revoked.is_empty() => PermissionState::Granted,
+        Some(allow) if allow.is_empty() => PermissionState::Denied,
+        _ => PermissionState::GrantedPartial,
+      };
+    };
+    if self.revoked.iter().any(|revoked| revoked.covers(desc)) {
+      return PermissionState::Denied;
+    }
+    let allowed = match &self.allow {
+      None => true,
+      Some(allow) => allow.iter().any(|allowed| allowed.covers(desc)),
+    };
+    match allowed {
+      true if self.revoked.iter().any(|revoked| desc.covers(revoked)) => {
+        PermissionState::GrantedPartial
+      }
+      true => PermissionState::Granted,
+      false => PermissionState::Denied,
+    }
+  }
+
+  fn revoke(&mut self, desc: Option<NetDescriptor>) -> PermissionState {
+    match desc {
+      Some(desc) => self.revoked.push(desc),
+      None => self.allow = Some(vec![]),
+    }
+    self.query(None)
+  }
+
+  /// The allow and deny lists of a `PermissionsContainer`'s net permission,
+  /// where an empty allow list grants every host. `None` if the container
+  /// can't express this permission, because a CIDR block or port range is
+  /// allowed or revoked, or because every allowed host was revoked.
+  fn container_lists(&self) -> Option<(Vec<String>, Vec<String>)> {
+    let deny = self
+      .revoked
+      .iter()
+      .map(NetDescriptor::to_container_host)
+      .collect::<Option<Vec<_>>>()?;
+    let Some(allow) = &self.allow else {
+      return Some((vec![], deny));
+    };
+    let allow = allow
+      .iter()
+      .filter(|desc| !self.revoked.iter().any(|revoked| revoked.covers(desc)))
+      .map(NetDescriptor::to_container_host)
+      .collect::<Option<Vec<_>>>()?;
+    match allow.is_empty() {
+      true => None,
+      false => Some((allow, deny)),
+    }
+  }
+
+  /// Replaces the net permission of `permissions` with this one, or with
+  /// one denying every host where `container_lists` can't express it.
+  fn narrow_container(
+    &self,
+    permissions: &mut ContainerPermissions,
+  ) -> Result<(), AnyError> {
+    permissions.net = match self.container_lists() {
+      Some((allow, deny)) => {
+        // An empty deny list would deny every host.
+        let deny = Some(deny).filter(|deny| !deny.is_empty());
+        ContainerPermissions::new_net(&Some(allow), &deny, false)?
+      }
+      // Without an allow list or prompts, every host is denied.
+      None => ContainerPermissions::new_net(&None, &None, false)?,
+    };
+    Ok(())
+  }
+}
+
+/// Permissions handed to the extensions, and to the module loader for
+/// `import`.
+///
+/// `net` gates outgoing connections and `import` gates loading modules by
+/// host. Every other permission is granted, as is every host by default.
+#[derive(Clone, Debug)]
+pub struct Permissions {
+  /// Whether `performance.now()` and friends get full precision.
+  pub hrtime: bool,
+  pub net: NetPermission,
+  pub import: NetPermission,
+}
+
+impl Default for Permissions {
+  fn default() -> Self {
+    Self {
+      hrtime: true,
+      net: Default::default(),
+      import: Default::default(),
+    }
+  }
+}
+
+impl Permissions {
+  fn check_net_url(&self, url: &Url, api_name: &str) -> Result<(), AnyError> {
+    let host = url.host_str().ok_or_else(|| uri_error("Missing host"))?;
+    self
+      .net
+      .check("net", host, url.port_or_known_default(), api_name)
+  }
+
+  /// The `PermissionsContainer` checked by the ops of `deno_runtime`, and
+  /// by every extension of a web worker, which `deno_runtime` creates with
+  /// the container in place of `Permissions`. The container can't express
+  /// CIDR blocks other than single addresses, nor port ranges, so if `net`
+  /// allows or revokes one, the container denies every host.
+  pub fn container(&self) -> Result<PermissionsContainer, AnyError> {
+    let mut permissions = ContainerPermissions::allow_all();
+    self.net.narrow_container(&mut permissions)?;
+    permissions.hrtime = ContainerPermissions::new_hrtime(self.hrtime, false);
+    Ok(PermissionsContainer::new(permissions))
+  }
+}
+
+/// The `import` permission of a runtime, shared between its module loader
+/// and `Deno.permissions`. See `module_loader::HostModuleLoader`.
+#[derive(Clone, Default)]
+pub struct ImportPermission(Rc<RefCell<NetPermission>>);
+
+impl ImportPermission {
+  pub fn new(permission: NetPermission) -> Self {
+    Self(Rc::new(RefCell::new(permission)))
+  }
+
+  /// Checks a module about to be loaded. Only remote modules are gated.
+  pub fn check(&self, specifier: &Url) -> Result<(), AnyError> {
+    if !matches!(specifier.scheme(), "http" | "https") {
+      return Ok(());
+    }
+    let host = specifier
+      .host_str()
+      .ok_or_else(|| uri_error("Missing host"))?;
+    self.0.borrow().check(
+      "import",
+      host,
+      specifier.port_or_known_default(),
+      "import()",
+    )
+  }
+}
+
+deno_core::extension!(
+  deno_host_permissions,
+  middleware = |op| match op.name {
+    "op_query_permission" => {
+      op.with_implementation_from(&op_host_query_permission::DECL)
+    }
+    "op_revoke_permission" => {
+      op.with_implementation_from(&op_host_revoke_permission::DECL)
+    }
+    "op_request_permission" => {
+      op.with_implementation_from(&op_host_request_permission::DECL)
+    }
+    _ => op,
+  },
+);
+
+#[derive(Deserialize)]
+pub struct PermissionArgs {
+  name: String,
+  path: Option<String>,
+  host: Option<String>,
+  variable: Option<String>,
+  kind: Option<String>,
+  command: Option<String>,
+}
+
+impl PermissionArgs {
+  fn net_descriptor(&self) -> Result<Option<NetDescriptor>, AnyError> {
+    self.host.as_deref().map(NetDescriptor::parse).transpose()
+  }
+}
+
+#[derive(Serialize)]
+pub struct PermissionStatus {
+  state: String,
+  partial: bool,
+}
+
+impl From<PermissionState> for PermissionStatus {
+  fn from(state: PermissionState) -> Self {
+    PermissionStatus {
+      state: if state == PermissionState::GrantedPartial {
+        PermissionState::Granted.to_string()
+      } else {
+        state.to_string()
+      },
+      partial: state == PermissionState::GrantedPartial,
+    }
+  }
+}
+
+#[derive(Clone, Copy)]
+enum PermissionOp {
+  Query,
+  Revoke,
+  Request,
+}
+
+/// `net`, `import` and `hrtime` are answered from `Permissions` and
+/// `ImportPermission`, which are what the runtime enforces. The remaining
+/// names are left to `PermissionsContainer`, as in `deno_runtime`.
+fn permission_status(
+  state: &mut OpState,
+  args: PermissionArgs,
+  op: PermissionOp,
+) -> Result<PermissionStatus, AnyError> {
+  let perm = match args.name.as_str() {
+    "net" => {
+      let desc = args.net_descriptor()?;
+      let net = &mut state.borrow_mut::<Permissions>().net;
+      match op {
+        PermissionOp::Revoke => {
+          let perm = net.revoke(desc);
+          let net = net.clone();
+          let container = state.borrow::<PermissionsContainer>();
+          net.narrow_container(&mut container.0.lock())?;
+          perm
+        }
+        PermissionOp::Query | PermissionOp::Request => net.query(desc.as_ref()),
+      }
+    }
+    "import" => {
+      let desc = args.net_descriptor()?;
+      let mut import = state.borrow::<ImportPermission>().0.borrow_mut();
+      match op {
+        PermissionOp::Revoke => import.revoke(desc),
+        PermissionOp::Query | PermissionOp::Request => {
+          import.query(desc.as_ref())
+        }
+      }
+    }
+    "hrtime" => {
+      let permissions = state.borrow_mut::<Permissions>();
+      if let PermissionOp::Revoke = op {
+        permissions.hrtime = false;
+      }
+      match permissions.hrtime {
+        true => PermissionState::Granted,
+        false => PermissionState::Denied,
+      }
+    }
+    _ => container_permission_status(state, &args, op)?,
+  };
+  Ok(PermissionStatus::from(perm))
+}
+
+fn container_permission_status(
+  state: &mut OpState,
+  args: &PermissionArgs,
+  op: PermissionOp,
+) -> Result<PermissionState, AnyError> {
+  let mut permissions = state.borrow_mut::<PermissionsContainer>().0.lock();
+  let path = args.path.as_deref().map(Path::new);
+  let kind = args.kind.as_deref().map(parse_sys_kind).transpose()?;
+  let perm = match (args.name.as_str(), op) {
+    ("read", PermissionOp::Query) => permissions.read.query(path),
+    ("read", PermissionOp::Revoke) => permissions.read.revoke(path),
+    ("read", PermissionOp::Request) => permissions.read.request(path),
+    ("write", PermissionOp::Query) => permissions.write.query(path),
+    ("write", PermissionOp::Revoke) => permissions.write.revoke(path),
+    ("write", PermissionOp::Request) => permissions.write.request(path),
+    ("env", PermissionOp::Query) => {
+      permissions.env.query(args.variable.as_deref())
+    }
+    ("env", PermissionOp::Revoke) => {
+      permissions.env.revoke(args.variable.as_deref())
+    }
+    ("env", PermissionOp::Request) => {
+      permissions.env.request(args.variable.as_deref())
+    }
+    ("sys", PermissionOp::Query) => permissions.sys.query(kind),
+    ("sys", PermissionOp::Revoke) => permissions.sys.revoke(kind),
+    ("sys", PermissionOp::Request) => permissions.sys.request(kind),
+    ("run", PermissionOp::Query) => {
+      permissions.run.query(args.command.as_deref())
+    }
+    ("run", PermissionOp::Revoke) => {
+      permissions.run.revoke(args.command.as_deref())
+    }
+    ("run", PermissionOp::Request) => {
+      permissions.run.request(args.command.as_deref())
+    }
+    ("ffi", PermissionOp::Query) => permissions.ffi.query(path),
+    ("ffi", PermissionOp::Revoke) => permissions.ffi.revoke(path),
+    ("ffi", PermissionOp::Request) => permissions.ffi.request(path),
+    (n, _) => {
+      return Err(custom_error(
+        "ReferenceError",
+        format!("No such permission name: {n}"),
+      ))
+    }
+  };
+  Ok(perm)
+}
+
+/// Replaces `op_query_permission`, see `permission_status`.
+#[op2]
+#[serde]
+fn op_host_query_permission(
+  state: &mut OpState,
+  #[serde] args: PermissionArgs,
+) -> Result<PermissionStatus, AnyError> {
+  permission_status(state, args, PermissionOp::Query)
+}
+
+/// Replaces `op_revoke_permission`, see `permission_status`.
+#[op2]
+#[serde]
+fn op_host_revoke_permission(
+  state: &mut OpState,
+  #[serde] args: PermissionArgs,
+) -> Result<PermissionStatus, AnyError> {
+  permission_status(state, args, PermissionOp::Revoke)
+}
+
+/// Replaces `op_request_permission`, see `permission_status`. The embedder
+/// never prompts, so this reports the same state as a query.
+#[op2]
+#[serde]
+fn op_host_request_permission(
+  state: &mut OpState,
+  #[serde] args: PermissionArgs,
+) -> Result<PermissionStatus, AnyError> {
+  permission_status(state, args, PermissionOp::Request)
+}
 
 impl deno_fetch::FetchPermissions for Permissions {
   fn check_net_url(
     &mut self,
-    _url: &deno_core::url::Url,
-    _api_name: &str,
+    url: &deno_core::url::Url,
+    api_name: &str,
   ) -> Result<(), deno_core::error::AnyError> {
-    Ok(())
+    Permissions::check_net_url(self, url, api_name)
   }
 
   fn check_read(
@@ -26,19 +599,20 @@ impl deno_fetch::FetchPermissions for Permissions {
 impl deno_websocket::WebSocketPermissions for Permissions {
   fn check_net_url(
     &mut self,
-    _url: &deno_core::url::Url,
-    _api_name: &str,
+    url: &deno_core::url::Url,
+    api_name: &str,
   ) -> Result<(), deno_core::error::AnyError> {
-    Ok(())
+    Permissions::check_net_url(self, url, api_name)
   }
 }
 
 impl deno_web::TimersPermission for Permissions {
   fn allow_hrtime(&mut self) -> bool {
-    true
+    self.hrtime
   }
 }
 
+#[cfg(feature = "ffi")]
 impl deno_ffi::FfiPermissions for Permissions {
   fn check_partial(
     &mut self,
@@ -48,6 +622,7 @@ impl deno_ffi::FfiPermissions for Permissions {
   }
 }
 
+#[cfg(feature = "napi")]
 impl deno_napi::NapiPermissions for Permissions {
   fn check(
     &mut self,
@@ -57,13 +632,14 @@ impl deno_napi::NapiPermissions for Permissions {
   }
 }
 
+#[cfg(feature = "node")]
 impl deno_node::NodePermissions for Permissions {
   fn check_net_url(
     &mut self,
-    _url: &deno_core::url::Url,
-    _api_name: &str,
+    url: &deno_core::url::Url,
+    api_name: &str,
   ) -> Result<(), deno_core::error::AnyError> {
-    Ok(())
+    Permissions::check_net_url(self, url, api_name)
   }
   fn check_read_with_api_name(
     &self,
@@ -91,10 +667,10 @@ impl deno_node::NodePermissions for Permissions {
 impl deno_net::NetPermissions for Permissions {
   fn check_net<T: AsRef<str>>(
     &mut self,
-    _host: &(T, Option<u16>),
-    _api_name: &str,
+    host: &(T, Option<u16>),
+    api_name: &str,
   ) -> Result<(), deno_core::error::AnyError> {
-    Ok(())
+    self.net.check("net", host.0.as_ref(), host.1, api_name)
   }
 
   fn check_read(
@@ -166,6 +742,7 @@ impl deno_fs::FsPermissions for Permissions {
   }
 }
 
+#[cfg(feature = "kv")]
 impl deno_kv::sqlite::SqliteDbHandlerPermissions for Permissions {
   fn check_read(
     &mut self,
@@ -183,3 +760,140 @@ impl deno_kv::sqlite::SqliteDbHandlerPermissions for Permissions {
     Ok(())
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::embed::Runtime;
+  use crate::testing::eval;
+  use crate::RunOptions;
+
+  #[test]
+  fn net_descriptor_cidr_and_port_ranges() {
+    let desc = NetDescriptor::parse("10.0.0.0/8").unwrap();
+    assert!(desc.matches("10.20.30.40", Some(80)));
+    assert!(!desc.matches("11.0.0.1", Some(80)));
+    assert!(!desc.matches("example.com", None));
+
+    let desc = NetDescriptor::parse("[fd00::/8]:8000-8999").unwrap();
+    assert!(desc.matches("[fd12::1]", Some(8080)));
+    assert!(!desc.matches("fd12::1", Some(9000)));
+    assert!(!desc.matches("fd12::1", None));
+
+    let desc = NetDescriptor::parse("example.com:443").unwrap();
+    assert!(desc.matches("example.com", Some(443)));
+    assert!(!desc.matches("example.com", Some(80)));
+
+    assert!(NetDescriptor::parse("10.0.0.0/33").is_err());
+    assert!(NetDescriptor::parse("example.com:9-1").is_err());
+  }
+
+  #[test]
+  fn import_checks_only_remote_specifiers() {
+    let import =
+      ImportPermission::new(NetPermission::allow(&["deno.land"]).unwrap());
+    let allowed = Url::parse("https://deno.land/std/mod.ts").unwrap();
+    let denied = Url::parse("https://example.com/mod.ts").unwrap();
+    let local = Url::parse("file:///plugin/mod.ts").unwrap();
+    assert!(import.check(&allowed).is_ok());
+    assert!(import.check(&denied).is_err());
+    assert!(import.check(&local).is_ok());
+  }
+
+  #[test]
+  fn container_denies_every_host_it_cannot_express() {
+    let url = |s: &str| Url::parse(s).unwrap();
+    let check = |net: NetPermission, s: &str| {
+      let permissions = Permissions {
+        net,
+        ..Default::default()
+      };
+      let mut container = permissions.container().unwrap();
+      container.check_net_url(&url(s), "test()").is_ok()
+    };
+
+    let net = NetPermission::allow(&["example.com"]).unwrap();
+    assert!(check(net.clone(), "http://example.com/"));
+    assert!(!check(net, "http://deno.land/"));
+
+    let mut net = NetPermission::default();
+    net.revoke(Some(NetDescriptor::parse("example.com").unwrap()));
+    assert!(!check(net.clone(), "http://example.com/"));
+    assert!(check(net, "http://deno.land/"));
+
+    let net = NetPermission::allow(&["10.0.0.0/8", "example.com"]).unwrap();
+    assert!(!check(net, "http://example.com/"));
+    let mut net = NetPermission::allow(&["example.com"]).unwrap();
+    net.revoke(None);
+    assert!(!check(net, "http://example.com/"));
+  }
+
+  fn sandboxed_runtime() -> Runtime {
+    Runtime::new(RunOptions {
+      permissions: Permissions {
+        net: NetPermission::allow(&["10.0.0.0/8", "example.com:443"]).unwrap(),
+        import: NetPermission::allow(&["deno.land"]).unwrap(),
+        ..Default::default()
+      },
+      ..Default::default()
+    })
+    .unwrap()
+  }
+
+  #[tokio::test]
+  async fn runtime_answers_queries_from_descriptors() {
+    let mut runtime = sandboxed_runtime();
+    let states: Vec<String> = eval(
+      &mut runtime,
+      r#"
+        const query = async (desc) => {
+          const status = await Deno.permissions.query(desc);
+          return status.partial ? "partial" : status.state;
+        };
+        return [
+          await query({ name: "net" }),
+          await query({ name: "net", host: "10.1.2.3:8080" }),
+          await query({ name: "net", host: "10.2.0.0/16" }),
+          await query({ name: "net", host: "192.168.0.1" }),
+          await query({ name: "net", host: "example.com:443" }),
+          await query({ name: "net", host: "example.com:80" }),
+          await query({ name: "import", host: "deno.land" }),
+          await query({ name: "import", host: "example.com" }),
+        ];
+      "#,
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+      states,
+      [
+        "partial", "granted", "granted", "denied", "granted", "denied",
+        "granted", "denied",
+      ]
+    );
+  }
+
+  #[tokio::test]
+  async fn runtime_denies_hosts_outside_descriptors() {
+    let mut runtime = sandboxed_runtime();
+    let errors: Vec<String> = eval(
+      &mut runtime,
+      r#"
+        const error = (promise) => promise.then(() => "", (err) => err.message);
+        await Deno.permissions.revoke({ name: "net", host: "10.0.0.1" });
+        return [
+          await error(fetch("http://192.168.0.1/")),
+          await error(Deno.connect({ hostname: "192.168.0.1", port: 80 })),
+          await error(fetch("http://10.0.0.1:8080/")),
+          await error(import("https://example.com/mod.js")),
+        ];
+      "#,
+    )
+    .await
+    .unwrap();
+    assert!(errors[0].contains("Requires net access to \"192.168.0.1:80\""));
+    assert!(errors[1].contains("Requires net access to \"192.168.0.1:80\""));
+    assert!(errors[2].contains("Requires net access to \"10.0.0.1:8080\""));
+    assert!(errors[3].contains("Requires import access to \"example.com:443\""));
+  }
+}
@@ -0,0 +1,388 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::mpsc as std_mpsc;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use deno_core::error::AnyError;
+use deno_core::serde_json::Value;
+use deno_core::url::Url;
+use deno_core::ModuleId;
+use tokio::sync::mpsc;
+use tokio::sync::oneshot;
+use tokio::sync::Mutex;
+
+use crate::deno_current_thread;
+use crate::embed::Runtime;
+use crate::exit::ExitOutcome;
+use crate::RunOptions;
+
+#[derive(Clone)]
+pub struct PoolOptions {
+  /// Number of isolates, each on its own thread. Defaults to the number of
+  /// available cores.
+  pub workers: usize,
+  /// Jobs that may wait for a free isolate before `WorkerPool::run` waits
+  /// for room in the queue.
+  pub queue_capacity: usize,
+  /// Replace an isolate with a fresh one after it has run this many jobs.
+  pub max_jobs_per_isolate: Option<usize>,
+  /// Wall-clock time a single job may take, including awaiting the promise
+  /// it returns.
+  pub job_timeout: Option<Duration>,
+  /// Options every isolate of the pool is created with.
+  pub run_options: RunOptions,
+}
+
+impl Default for PoolOptions {
+  fn default() -> Self {
+    let workers = std::thread::available_parallelism()
+      .map(|n| n.get())
+      .unwrap_or(1);
+    Self {
+      workers,
+      queue_capacity: workers * 4,
+      max_jobs_per_isolate: None,
+      job_timeout: None,
+      run_options: Default::default(),
+    }
+  }
+}
+
+/// A call of an exported function.
+///
+/// The module is loaded once per isolate and cached by `specifier`, from
+/// `code` if given or else through the module loader. `args` is spread into
+/// positional arguments when it is an array.
+#[derive(Clone, Debug)]
+pub struct Job {
+  pub specifier: Url,
+  pub code: Option<String>,
+  pub export: String,
+  pub args: Value,
+}
+
+#[derive(Debug)]
+pub enum JobError {
+  /// The job took longer than `PoolOptions::job_timeout`.
+  Timeout(Duration),
+  /// The isolate was terminated, by `Deno.exit()` or a resource limit.
+  Terminated(ExitOutcome),
+  /// Loading the module or calling the export failed.
+  Js(AnyError),
+  /// No isolate could be created to run the job.
+  Startup(AnyError),
+  /// The pool was shut down before the job ran.
+  PoolClosed,
+}
+
+impl fmt::Display for JobError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      JobError::Timeout(limit) => write!(f, "Job timed out after {limit:?}"),
+      JobError::Terminated(ExitOutcome::Terminated(Some(exceeded))) => {
+        write!(f, "{exceeded}")
+      }
+      JobError::Terminated(outcome) => {
+        write!(f, "Isolate terminated during job: {outcome:?}")
+      }
+      JobError::Js(err) => write!(f, "{err}"),
+      JobError::Startup(err) => write!(f, "Failed to start isolate: {err}"),
+      JobError::PoolClosed => write!(f, "Worker pool is closed"),
+    }
+  }
+}
+
+impl std::error::Error for JobError {}
+
+type JobResult = Result<Value, JobError>;
+
+struct QueuedJob {
+  job: Job,
+  respond_to: oneshot::Sender<JobResult>,
+}
+
+/// A fixed set of isolates that run jobs in parallel.
+///
+/// Each worker thread owns a current-thread Tokio runtime and one isolate
+/// created from the shared snapshot. Idle workers take jobs from a bounded
+/// queue, so `run` applies back-pressure once `queue_capacity` jobs are
+/// waiting. An isolate is replaced after a timeout or termination, and after
+/// `max_jobs_per_isolate` jobs.
+///
+/// ```ignore
+/// let pool = WorkerPool::new(PoolOptions::default());
+/// let output = pool.run(Job {
+///   specifier: Url::parse("file:///plugins/transform.js")?,
+///   code: None,
+///   export: "transform".to_string(),
+///   args: json!([source, path]),
+/// }).await?;
+/// ```
+pub struct WorkerPool {
+  sender: Option<mpsc::Sender<QueuedJob>>,
+  threads: Vec<JoinHandle<()>>,
+}
+
+impl WorkerPool {
+  pub fn new(options: PoolOptions) -> Self {
+    let (sender, receiver) = mpsc::channel(options.queue_capacity.max(1));
+    let receiver = Arc::new(Mutex::new(receiver));
+    let threads = (0..options.workers.max(1))
+      .map(|i| {
+        let receiver = receiver.clone();
+        let options = options.clone();
+        std::thread::Builder::new()
+          .name(format!("js-worker-{i}"))
+          .spawn(move || deno_current_thread(worker_loop(receiver, options)))
+          .unwrap()
+      })
+      .collect();
+    Self {
+      sender: Some(sender),
+      threads,
+    }
+  }
+
+  /// Queues `job`, waiting for room in the queue if it is full, and
+  /// resolves with the value returned by the export.
+  pub async fn run(&self, job: Job) -> JobResult {
+    let (respond_to, response) = oneshot::channel();
+    let sender = self.sender.as_ref().ok_or(JobError::PoolClosed)?;
+    sender
+      .send(QueuedJob { job, respond_to })
+      .await
+      .map_err(|_| JobError::PoolClosed)?;
+    response.await.unwrap_or(Err(JobError::PoolClosed))
+  }
+
+  /// Like `run`, for callers outside of an async context.
+  pub fn run_blocking(&self, job: Job) -> JobResult {
+    let (respond_to, response) = oneshot::channel();
+    let sender = self.sender.as_ref().ok_or(JobError::PoolClosed)?;
+    sender
+      .blocking_send(QueuedJob { job, respond_to })
+      .map_err(|_| JobError::PoolClosed)?;
+    response.blocking_recv().unwrap_or(Err(JobError::PoolClosed))
+  }
+}
+
+impl Drop for WorkerPool {
+  /// Closes the queue, lets the workers finish the jobs already queued and
+  /// waits for their threads to exit.
+  fn drop(&mut self) {
+    self.sender.take();
+    for thread in self.threads.drain(..) {
+      let _ = thread.join();
+    }
+  }
+}
+
+/// An isolate together with the modules already loaded into it.
+struct PooledRuntime {
+  runtime: Runtime,
+  modules: HashMap<Url, ModuleId>,
+  jobs: usize,
+}
+
+impl PooledRuntime {
+  fn new(options: &RunOptions) -> Result<Self, AnyError> {
+    Ok(Self {
+      runtime: Runtime::new(options.clone())?,
+      modules: HashMap::new(),
+      jobs: 0,
+    })
+  }
+
+  async fn call(&mut self, job: Job) -> Result<Value, AnyError> {
+    let module_id = match self.modules.get(&job.specifier) {
+      Some(module_id) => *module_id,
+      None => {
+        let module_id = self
+          .runtime
+          .load_side_module(&job.specifier, job.code)
+          .await?;
+        self.modules.insert(job.specifier, module_id);
+        module_id
+      }
+    };
+    let export = self.runtime.get_export(module_id, &job.export)?;
+    self.runtime.call_async(&export, job.args).await
+  }
+}
+
+async fn worker_loop(
+  receiver: Arc<Mutex<mpsc::Receiver<QueuedJob>>>,
+  options: PoolOptions,
+) {
+  let mut pooled = None;
+  loop {
+    let Some(QueuedJob { job, respond_to }) = receiver.lock().await.recv().await
+    else {
+      return;
+    };
+    if pooled.is_none() {
+      match PooledRuntime::new(&options.run_options) {
+        Ok(runtime) => pooled = Some(runtime),
+        Err(err) => {
+          let _ = respond_to.send(Err(JobError::Startup(err)));
+          continue;
+        }
+      }
+    }
+    let runtime = pooled.as_mut().unwrap();
+    runtime.jobs += 1;
+
+    let timer = options.job_timeout.map(|limit| {
+      JobTimer::start(&mut runtime.runtime, limit)
+    });
+    let call = runtime.call(job);
+    let result = match options.job_timeout {
+      // Terminating the isolate does not interrupt a job that is awaiting
+      // an async op, so the call itself is bounded too.
+      Some(limit) => tokio::time::timeout(limit, call).await.ok(),
+      None => Some(call.await),
+    };
+    let timer_fired = timer.map(|timer| timer.finish()).unwrap_or(false);
+    let result = result.filter(|_| !timer_fired);
+
+    let result = match (result, runtime.runtime.terminated()) {
+      (None, _) => Err(JobError::Timeout(options.job_timeout.unwrap())),
+      (Some(_), Some(outcome)) => Err(JobError::Terminated(outcome)),
+      (Some(result), None) => result.map_err(JobError::Js),
+    };
+    // A terminated isolate cannot run further jobs, and one that timed out
+    // may still have the job's work pending.
+    let recycle = result.as_ref().is_err_and(|err| {
+      matches!(err, JobError::Timeout(_) | JobError::Terminated(_))
+    }) || options
+      .max_jobs_per_isolate
+      .is_some_and(|max| runtime.jobs >= max);
+    if recycle {
+      pooled = None;
+    }
+    let _ = respond_to.send(result);
+  }
+}
+
+/// Terminates the isolate if a job is still running when the timeout
+/// elapses. Runs on its own thread so that jobs stuck in synchronous JS are
+/// interrupted too.
+struct JobTimer {
+  done: std_mpsc::Sender<()>,
+  thread: JoinHandle<bool>,
+}
+
+impl JobTimer {
+  fn start(runtime: &mut Runtime, limit: Duration) -> Self {
+    let isolate_handle =
+      runtime.js_runtime().v8_isolate().thread_safe_handle();
+    let (done, done_rx) = std_mpsc::channel::<()>();
+    let thread = std::thread::spawn(move || {
+      match done_rx.recv_timeout(limit) {
+        Err(std_mpsc::RecvTimeoutError::Timeout) => {
+          isolate_handle.terminate_execution();
+          true
+        }
+        _ => false,
+      }
+    });
+    Self { done, thread }
+  }
+
+  /// Stops the timer and returns whether it fired.
+  fn finish(self) -> bool {
+    let _ = self.done.send(());
+    self.thread.join().unwrap_or(false)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use deno_core::serde_json::json;
+
+  const MODULE: &str = r#"
+    let calls = 0;
+    export function count() {
+      return ++calls;
+    }
+    export function spin() {
+      while (true) {}
+    }
+    export function sleep() {
+      return new Promise((resolve) => setTimeout(resolve, 60_000));
+    }
+  "#;
+
+  fn job(export: &str) -> Job {
+    Job {
+      specifier: Url::parse("file:///test/pool.js").unwrap(),
+      code: Some(MODULE.to_string()),
+      export: export.to_string(),
+      args: json!([]),
+    }
+  }
+
+  fn single_worker_pool(options: PoolOptions) -> WorkerPool {
+    WorkerPool::new(PoolOptions {
+      workers: 1,
+      ..options
+    })
+  }
+
+  #[test]
+  fn isolate_is_recycled_after_max_jobs() {
+    let pool = single_worker_pool(PoolOptions {
+      max_jobs_per_isolate: Some(2),
+      ..Default::default()
+    });
+    let counts: Vec<Value> = (0..3)
+      .map(|_| pool.run_blocking(job("count")).unwrap())
+      .collect();
+    assert_eq!(counts, [json!(1), json!(2), json!(1)]);
+  }
+
+  #[test]
+  fn sync_job_times_out_and_isolate_is_recycled() {
+    let limit = Duration::from_millis(200);
+    let pool = single_worker_pool(PoolOptions {
+      job_timeout: Some(limit),
+      ..Default::default()
+    });
+    assert_eq!(pool.run_blocking(job("count")).unwrap(), json!(1));
+    let err = pool.run_blocking(job("spin")).unwrap_err();
+    assert!(matches!(err, JobError::Timeout(l) if l == limit), "{err}");
+    assert_eq!(pool.run_blocking(job("count")).unwrap(), json!(1));
+  }
+
+  #[test]
+  fn async_job_times_out() {
+    let limit = Duration::from_millis(200);
+    let pool = single_worker_pool(PoolOptions {
+      job_timeout: Some(limit),
+      ..Default::default()
+    });
+    let err = pool.run_blocking(job("sleep")).unwrap_err();
+    assert!(matches!(err, JobError::Timeout(l) if l == limit), "{err}");
+    assert_eq!(pool.run_blocking(job("count")).unwrap(), json!(1));
+  }
+
+  #[test]
+  fn exit_terminates_the_job() {
+    let pool = single_worker_pool(PoolOptions::default());
+    let err = pool
+      .run_blocking(Job {
+        specifier: Url::parse("file:///test/pool_exit.js").unwrap(),
+        code: Some("export function exit() { Deno.exit(4); }".to_string()),
+        export: "exit".to_string(),
+        args: json!([]),
+      })
+      .unwrap_err();
+    assert!(
+      matches!(err, JobError::Terminated(ExitOutcome::Exited { code: 4 })),
+      "{err}"
+    );
+  }
+}
@@ -0,0 +1,25 @@
+//! Helpers for tests that run JS in a `Runtime`.
+
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+
+use deno_core::error::AnyError;
+use deno_core::serde::de::DeserializeOwned;
+use deno_core::url::Url;
+
+use crate::embed::Runtime;
+
+/// Evaluates `body` as the body of an async function in `runtime` and
+/// returns its result.
+pub async fn eval<T: DeserializeOwned>(
+  runtime: &mut Runtime,
+  body: &str,
+) -> Result<T, AnyError> {
+  static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+  let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+  let specifier = Url::parse(&format!("file:///test/eval_{id}.js")).unwrap();
+  let code = format!("export default async function () {{\n{body}\n}}\n");
+  let module_id = runtime.load_side_module(&specifier, Some(code)).await?;
+  let function = runtime.get_export(module_id, "default")?;
+  runtime.call_async(&function, ()).await
+}
@@ -2,8 +2,20 @@ use std::sync::Arc;
 use deno_runtime::web_worker as deno_web_worker;
 use deno_runtime::ops::worker_host::CreateWebWorkerCb;
 use deno_web_worker::WebWorkerOptions;
+use crate::exit;
+use crate::exit::ExitHandler;
+use crate::limits::WorkerLimits;
+use crate::op_filter::OpFilter;
+use crate::module_loader::HostModuleLoader;
+use crate::permissions;
+use crate::permissions::ImportPermission;
+use crate::permissions::Permissions;
+use host_extensions::extensions::check_snapshot_ops;
+use host_extensions::extensions::op_recorder;
+use host_extensions::HostState;
 // Copyright 2018-2024 the Deno authors. All rights reserved. MIT license.
 
+use std::cell::OnceCell;
 use std::path::Path;
 use std::path::PathBuf;
 use std::rc::Rc;
@@ -38,6 +50,8 @@ use deno_runtime::fmt_errors::format_js_error;
 use deno_runtime::inspector_server::InspectorServer;
 use deno_runtime::permissions::PermissionsContainer;
 use deno_runtime::web_worker::WebWorker;
+use deno_runtime::web_worker::WebWorkerInternalHandle;
+use deno_runtime::web_worker::WorkerControlEvent;
 use deno_runtime::worker::MainWorker;
 use deno_runtime::worker::WorkerOptions;
 use deno_runtime::BootstrapOptions;
@@ -129,7 +143,12 @@ pub struct SharedWorkerState {
   pub argv0: Option<String>,
   pub skip_op_registration: bool,
   pub maybe_root_package_json_deps: Option<()>,
-
+  pub limits: WorkerLimits,
+  pub op_filter: OpFilter,
+  pub host_state: HostState,
+  /// See `Deterministic`.
+  pub seed: Option<u64>,
+  pub permissions: Permissions,
 }
 
 impl SharedWorkerState {
@@ -174,6 +193,20 @@ pub fn create_web_worker_callback(
     //   }
     // }
 
+    // `Deno.exit()` closes the worker, like `self.close()`, rather than
+    // exiting the process. The handle only exists once the worker does.
+    let exit_handle = Rc::new(OnceCell::<WebWorkerInternalHandle>::new());
+    let exit_handler = {
+      let exit_handle = exit_handle.clone();
+      ExitHandler(Rc::new(move |_code| {
+        if let Some(handle) = exit_handle.get() {
+          handle.clone().terminate();
+        }
+      }))
+    };
+    let import = ImportPermission::new(shared.permissions.import.clone());
+
+    let (recorder, ops) = op_recorder();
     let options = WebWorkerOptions {
       bootstrap: BootstrapOptions {
         args: shared.argv.clone(),
@@ -198,15 +231,24 @@ pub fn create_web_worker_callback(
         verbose_deprecated_api_warning: false, //shared.verbose_deprecated_api_warning,
         future: false,
       },
-      extensions: vec![],
+      extensions: host_extensions::runtime_extensions(&shared.host_state)
+        .into_iter()
+        .chain([
+          exit::deno_host_exit::init_ops(exit_handler),
+          permissions::deno_host_permissions::init_ops(),
+          shared.op_filter.extension(),
+          recorder,
+        ])
+        .collect(),
       startup_snapshot: Some(crate::SNAPSHOT),
+      create_params: shared.limits.create_params(),
       unsafely_ignore_certificate_errors: None,// true, //
       root_cert_store_provider: None, //Some(shared.root_cert_store_provider.clone()),
-      seed: None,//shared.options.seed,
+      seed: shared.seed,
       create_web_worker_cb,
       format_js_error_fn: Some(Arc::new(format_js_error)),
       source_map_getter: None,//maybe_source_map_getter,
-      module_loader: Rc::new(deno_core::FsModuleLoader),
+      module_loader: Rc::new(HostModuleLoader::new(import.clone())),
       fs: shared.fs.clone(),
       npm_resolver: None,//Some(shared.npm_resolver.clone().into_npm_resolver()),
       worker_type: args.worker_type,
@@ -223,12 +265,169 @@ pub fn create_web_worker_callback(
       feature_checker: Arc::new(FeatureChecker::default()),
     };
 
-    WebWorker::bootstrap_from_options(
+    // Rather than the parent's, which `deno_runtime` hands out, so that the
+    // worker's extensions are narrowed to the same hosts.
+    let permissions = shared
+      .permissions
+      .container()
+      .expect("permissions were validated by Runtime::new");
+    let (mut worker, handle) = WebWorker::bootstrap_from_options(
       args.name,
-      args.permissions,
+      permissions,
       args.main_module,
       args.worker_id,
       options,
-    )
+    );
+
+    let internal_handle = worker
+      .js_runtime
+      .op_state()
+      .borrow()
+      .borrow::<WebWorkerInternalHandle>()
+      .clone();
+    let _ = exit_handle.set(internal_handle.clone());
+    {
+      // Read by the ops of `deno_host_permissions`.
+      let op_state = worker.js_runtime.op_state();
+      let mut state = op_state.borrow_mut();
+      state.put(shared.permissions.clone());
+      state.put(import);
+    }
+    // `deno_runtime` builds the worker from its own extension list, which
+    // has to register the same ops as the snapshot's. A mismatch is reported
+    // to the parent and the worker never runs its main module.
+    let ops = ops.borrow().clone();
+    if let Err(err) = check_snapshot_ops(&mut worker.js_runtime, &ops) {
+      let _ =
+        internal_handle.post_event(WorkerControlEvent::TerminalError(err));
+      worker.js_runtime.v8_isolate().terminate_execution();
+    }
+
+    // This callback runs on the worker's own thread, so the CPU budget is
+    // measured against the right thread. The watchdog lives in the op state
+    // and is dropped together with the worker. A violation is posted to the
+    // parent as a terminal error before the worker's execution is
+    // terminated, so its `error` event names the limit rather than the
+    // generic termination error that follows.
+    let internal_handle = Mutex::new(internal_handle);
+    let watchdog =
+      shared
+        .limits
+        .install_with(&mut worker.js_runtime, move |exceeded| {
+          let _ = internal_handle.lock().post_event(
+            WorkerControlEvent::TerminalError(exceeded.clone().into()),
+          );
+        });
+    worker.js_runtime.op_state().borrow_mut().put(watchdog);
+
+    (worker, handle)
   })
 }
+
+#[cfg(test)]
+mod tests {
+  use deno_core::serde::de::DeserializeOwned;
+  use deno_core::url::Url;
+
+  use crate::embed::Runtime;
+  use crate::permissions::NetPermission;
+  use crate::permissions::Permissions;
+  use crate::testing::eval;
+  use crate::RunOptions;
+
+  /// Runs `source` as a module worker of `runtime`, posts it `"ping"` and
+  /// returns its first reply.
+  async fn worker_reply<T: DeserializeOwned>(
+    runtime: &mut Runtime,
+    source: &str,
+  ) -> T {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("worker.js");
+    std::fs::write(&path, source).unwrap();
+    let url = Url::from_file_path(&path).unwrap();
+    eval(
+      runtime,
+      &format!(
+        r#"
+          const worker = new Worker("{url}", {{ type: "module" }});
+          const reply = new Promise((resolve, reject) => {{
+            worker.onmessage = (event) => resolve(event.data);
+            worker.onerror = (event) => {{
+              event.preventDefault();
+              reject(new Error(event.message));
+            }};
+          }});
+          worker.postMessage("ping");
+          try {{
+            return await reply;
+          }} finally {{
+            worker.terminate();
+          }}
+        "#
+      ),
+    )
+    .await
+    .unwrap()
+  }
+
+  #[tokio::test]
+  async fn web_worker_matches_snapshot() {
+    let mut runtime = Runtime::new(RunOptions::default()).unwrap();
+    let echoed: String = worker_reply(
+      &mut runtime,
+      "self.onmessage = (e) => self.postMessage(e.data);",
+    )
+    .await;
+    assert_eq!(echoed, "ping");
+  }
+
+  #[tokio::test]
+  async fn web_worker_answers_and_enforces_the_runtime_permissions() {
+    let mut runtime = Runtime::new(RunOptions {
+      permissions: Permissions {
+        net: NetPermission::allow(&["10.0.0.0/8", "example.com"]).unwrap(),
+        import: NetPermission::allow(&["deno.land"]).unwrap(),
+        ..Default::default()
+      },
+      ..Default::default()
+    })
+    .unwrap();
+    let replies: Vec<String> = worker_reply(
+      &mut runtime,
+      r#"
+        const state = async (desc) => (await Deno.permissions.query(desc)).state;
+        const error = (promise) => promise.then(() => "", (err) => err.message);
+        self.onmessage = async () => self.postMessage([
+          await state({ name: "import", host: "deno.land" }),
+          await state({ name: "import", host: "example.com" }),
+          await error(fetch("http://example.com/")),
+          await error(fetch("http://10.0.0.1/")),
+        ]);
+      "#,
+    )
+    .await;
+    assert_eq!(replies[..2], ["granted", "denied"]);
+    // The worker's `PermissionsContainer` can't express the CIDR block, so
+    // it denies every host rather than allowing them all.
+    assert!(replies[2].contains("Requires net access to \"example.com:80\""));
+    assert!(replies[3].contains("Requires net access to \"10.0.0.1:80\""));
+  }
+
+  #[tokio::test]
+  async fn web_worker_exit_closes_only_the_worker() {
+    let mut runtime = Runtime::new(RunOptions::default()).unwrap();
+    let reply: String = worker_reply(
+      &mut runtime,
+      r#"
+        self.onmessage = () => {
+          self.postMessage("exiting");
+          Deno.exit(3);
+        };
+      "#,
+    )
+    .await;
+    assert_eq!(reply, "exiting");
+    let alive: bool = eval(&mut runtime, "return true;").await.unwrap();
+    assert!(alive);
+  }
+}
@@ -0,0 +1,51 @@
+// Copyright 2018-2024 the Deno authors. All rights reserved. MIT license.
+
+// A minimal bootstrap entry for snapshots built with the
+// `exclude_runtime_main_js` feature, used in place of `js/99_main.js`:
+//
+//   cargo run --features exclude_runtime_main_js -- examples/bootstrap.js
+//
+// deno_core rejects snapshots with extension modules that are never
+// imported, so the entry has to import every `ext:runtime/*` module, directly
+// or through another one. `globalThis.bootstrap` is what `MainWorker` and
+// `WebWorker` call once the snapshot has been deserialized.
+
+import { primordials } from "ext:core/mod.js";
+import { denoNs } from "ext:runtime/90_deno_ns.js";
+import { windowOrWorkerGlobalScope } from "ext:runtime/98_global_scope_shared.js";
+import { mainRuntimeGlobalProperties } from "ext:runtime/98_global_scope_window.js";
+import { workerRuntimeGlobalProperties } from "ext:runtime/98_global_scope_worker.js";
+const {
+  ObjectDefineProperties,
+  ObjectDefineProperty,
+  ObjectFreeze,
+} = primordials;
+
+ObjectDefineProperties(globalThis, windowOrWorkerGlobalScope);
+
+function exposeDeno() {
+  ObjectDefineProperty(globalThis, "Deno", {
+    value: ObjectFreeze({ ...denoNs }),
+    enumerable: false,
+    writable: false,
+    configurable: false,
+  });
+}
+
+function bootstrapMainRuntime(_runtimeOptions) {
+  delete globalThis.bootstrap;
+  ObjectDefineProperties(globalThis, mainRuntimeGlobalProperties);
+  exposeDeno();
+}
+
+function bootstrapWorkerRuntime(_runtimeOptions, name, _internalName) {
+  delete globalThis.bootstrap;
+  ObjectDefineProperties(globalThis, workerRuntimeGlobalProperties);
+  ObjectDefineProperty(globalThis, "name", { value: name, writable: true });
+  exposeDeno();
+}
+
+globalThis.bootstrap = {
+  mainRuntime: bootstrapMainRuntime,
+  workerRuntime: bootstrapWorkerRuntime,
+};
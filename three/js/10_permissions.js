@@ -37,11 +37,12 @@ const illegalConstructorKey = Symbol("illegalConstructorKey");
  * @property {boolean} partial
  */
 
-/** @type {ReadonlyArray<"read" | "write" | "net" | "env" | "sys" | "run" | "ffi" | "hrtime">} */
+/** @type {ReadonlyArray<"read" | "write" | "net" | "import" | "env" | "sys" | "run" | "ffi" | "hrtime">} */
 const permissionNames = [
   "read",
   "write",
   "net",
+  "import",
   "env",
   "sys",
   "run",
@@ -137,7 +138,7 @@ function cache(desc, rawStatus) {
     ReflectHas(desc, "path")
   ) {
     key += `-${desc.path}&`;
-  } else if (desc.name === "net" && desc.host) {
+  } else if ((desc.name === "net" || desc.name === "import") && desc.host) {
     key += `-${desc.host}&`;
   } else if (desc.name === "run" && desc.command) {
     key += `-${desc.command}&`;
@@ -282,7 +283,13 @@ function serializePermissions(permissions) {
       }
     }
     for (
-      const key of new SafeArrayIterator(["env", "hrtime", "net", "sys"])
+      const key of new SafeArrayIterator([
+        "env",
+        "hrtime",
+        "net",
+        "import",
+        "sys",
+      ])
     ) {
       if (ArrayIsArray(permissions[key])) {
         serializedPermissions[key] = ArrayPrototypeSlice(permissions[key]);
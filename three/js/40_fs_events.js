@@ -19,8 +19,25 @@ class FsWatcher {
   #rid = 0;
 
   constructor(paths, options) {
-    const { recursive } = options;
-    this.#rid = op_fs_events_open({ recursive, paths });
+    const {
+      recursive,
+      debounce,
+      queueCapacity,
+      include,
+      exclude,
+      usePolling,
+      pollInterval,
+    } = options;
+    this.#rid = op_fs_events_open({
+      recursive,
+      paths,
+      debounce,
+      queueCapacity,
+      include,
+      exclude,
+      usePolling,
+      pollInterval,
+    });
   }
 
   get rid() {
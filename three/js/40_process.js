@@ -3,12 +3,16 @@
 import { core, internals, primordials } from "ext:core/mod.js";
 import {
   op_kill,
+  op_pty_resize,
   op_run,
   op_run_status,
   op_spawn_child,
   op_spawn_kill,
+  op_spawn_kill_group,
+  op_spawn_read_line,
   op_spawn_sync,
   op_spawn_wait,
+  op_spawn_wait_cancelable,
 } from "ext:core/ops";
 const {
   ArrayPrototypeMap,
@@ -25,6 +29,7 @@ const {
 } = primordials;
 
 import { FsFile } from "ext:deno_fs/30_fs.js";
+import { errors } from "ext:runtime/01_errors.js";
 import { readAll } from "ext:deno_io/12_io.js";
 import {
   assert,
@@ -41,6 +46,23 @@ import {
   writableStreamForRid,
 } from "ext:deno_web/06_streams.js";
 
+// Without the `deprecated_run` feature the profile always disables
+// `Deno.run()`, as its ops are disabled.
+let deprecatedRunEnabled = true;
+
+// Called while bootstrapping if the `BootstrapProfile` disables `Deno.run()`.
+internals.disableDeprecatedRun = () => {
+  deprecatedRunEnabled = false;
+};
+
+function checkDeprecatedRun(apiName) {
+  if (!deprecatedRunEnabled) {
+    throw new errors.NotSupported(
+      `${apiName} is not available in this runtime, use Deno.Command instead`,
+    );
+  }
+}
+
 function opKill(pid, signo, apiName) {
   op_kill(pid, signo, apiName);
 }
@@ -54,6 +76,7 @@ function opRunStatus(rid) {
 }
 
 function opRun(request) {
+  checkDeprecatedRun("Deno.run()");
   assert(request.cmd.length > 0);
   return op_run(request);
 }
@@ -175,6 +198,15 @@ function spawnChildInner(opFn, command, apiName, {
   env = {},
   uid = undefined,
   gid = undefined,
+  rlimits = undefined,
+  processGroup = false,
+  setsid = false,
+  killOnDrop = false,
+  ptySize = undefined,
+  mergeOutput = false,
+  timeout = undefined,
+  killSignal = undefined,
+  killDelay = undefined,
   stdin = "null",
   stdout = "piped",
   stderr = "piped",
@@ -190,6 +222,15 @@ function spawnChildInner(opFn, command, apiName, {
     env: ObjectEntries(env),
     uid,
     gid,
+    rlimits,
+    processGroup,
+    setsid,
+    killOnDrop,
+    ptySize,
+    mergeOutput,
+    timeout,
+    killSignal,
+    killDelay,
     stdin,
     stdout,
     stderr,
@@ -199,6 +240,8 @@ function spawnChildInner(opFn, command, apiName, {
   return new ChildProcess(illegalConstructorKey, {
     ...child,
     signal,
+    processGroup: processGroup || setsid,
+    mergeOutput,
   });
 }
 
@@ -229,6 +272,7 @@ class ChildProcess {
   #rid;
   #waitPromise;
   #waitComplete = false;
+  #processGroup = false;
 
   [_pipeFd];
 
@@ -245,6 +289,8 @@ class ChildProcess {
     return this.#stdin;
   }
 
+  #stdoutRid = null;
+  #mergeOutput = false;
   #stdout = null;
   get stdout() {
     if (this.#stdout == null) {
@@ -255,19 +301,33 @@ class ChildProcess {
 
   #stderr = null;
   get stderr() {
+    if (this.#mergeOutput) {
+      throw new TypeError("stderr is merged into stdout");
+    }
     if (this.#stderr == null) {
       throw new TypeError("stderr is not piped");
     }
     return this.#stderr;
   }
 
+  #pty = null;
+  get pty() {
+    if (this.#pty == null) {
+      throw new TypeError("No stdio is a pty");
+    }
+    return this.#pty;
+  }
+
   constructor(key = null, {
     signal,
+    processGroup,
+    mergeOutput,
     rid,
     pid,
     stdinRid,
     stdoutRid,
     stderrRid,
+    ptyRid,
     pipeFd, // internal
   } = null) {
     if (key !== illegalConstructorKey) {
@@ -276,6 +336,8 @@ class ChildProcess {
 
     this.#rid = rid;
     this.#pid = pid;
+    this.#processGroup = processGroup;
+    this.#mergeOutput = mergeOutput;
     this[_pipeFd] = pipeFd;
 
     if (stdinRid !== null) {
@@ -283,6 +345,7 @@ class ChildProcess {
     }
 
     if (stdoutRid !== null) {
+      this.#stdoutRid = stdoutRid;
       this.#stdout = readableStreamForRidUnrefable(stdoutRid);
     }
 
@@ -290,13 +353,32 @@ class ChildProcess {
       this.#stderr = readableStreamForRidUnrefable(stderrRid);
     }
 
-    const onAbort = () => this.kill("SIGTERM");
+    if (ptyRid !== null) {
+      this.#pty = {
+        readable: readableStreamForRidUnrefable(ptyRid),
+        // The readable closes the pty once the child hung up, closing the
+        // writable must not.
+        writable: writableStreamForRid(ptyRid, false),
+        resize: ({ cols, rows }) => op_pty_resize(ptyRid, cols, rows),
+      };
+    }
+
+    // Aborting also cancels the wait, which then sends SIGKILL if the child
+    // still runs after `killDelay`.
+    const cancelRid = signal ? core.createCancelHandle() : null;
+    const onAbort = () => {
+      core.tryClose(cancelRid);
+      this.kill("SIGTERM");
+    };
     signal?.[abortSignal.add](onAbort);
 
-    const waitPromise = op_spawn_wait(this.#rid);
+    const waitPromise = cancelRid === null
+      ? op_spawn_wait(this.#rid)
+      : op_spawn_wait_cancelable(this.#rid, cancelRid);
     this.#waitPromise = waitPromise;
     this.#status = PromisePrototypeThen(waitPromise, (res) => {
       signal?.[abortSignal.remove](onAbort);
+      if (cancelRid !== null) core.tryClose(cancelRid);
       this.#waitComplete = true;
       return res;
     });
@@ -329,6 +411,8 @@ class ChildProcess {
       success: status.success,
       code: status.code,
       signal: status.signal,
+      timedOut: status.timedOut,
+      usage: status.usage,
       get stdout() {
         if (stdout == null) {
           throw new TypeError("stdout is not piped");
@@ -344,16 +428,44 @@ class ChildProcess {
     };
   }
 
-  kill(signo = "SIGTERM") {
+  /**
+   * Reads the merged output line by line, without line terminators. Don't
+   * read `stdout` at the same time.
+   */
+  async *lines() {
+    if (!this.#mergeOutput) {
+      throw new TypeError("lines() requires mergeOutput");
+    }
+    if (this.#stdout.locked) {
+      throw new TypeError("Can't read lines because stdout is locked");
+    }
+    while (true) {
+      const line = await op_spawn_read_line(this.#stdoutRid);
+      if (line === null) {
+        return;
+      }
+      yield line;
+    }
+  }
+
+  kill(signo = "SIGTERM", { group = false } = {}) {
     if (this.#waitComplete) {
       throw new TypeError("Child process has already terminated.");
     }
-    op_spawn_kill(this.#rid, signo);
+    if (group) {
+      op_spawn_kill_group(this.#rid, signo);
+    } else {
+      op_spawn_kill(this.#rid, signo);
+    }
   }
 
   async [SymbolAsyncDispose]() {
     try {
-      op_spawn_kill(this.#rid, "SIGTERM");
+      if (this.#processGroup) {
+        op_spawn_kill_group(this.#rid, "SIGTERM");
+      } else {
+        op_spawn_kill(this.#rid, "SIGTERM");
+      }
     } catch {
       // ignore errors from killing the process (such as ESRCH or BadResource)
     }
@@ -364,12 +476,14 @@ class ChildProcess {
     core.refOpPromise(this.#waitPromise);
     if (this.#stdout) readableStreamForRidUnrefableRef(this.#stdout);
     if (this.#stderr) readableStreamForRidUnrefableRef(this.#stderr);
+    if (this.#pty) readableStreamForRidUnrefableRef(this.#pty.readable);
   }
 
   unref() {
     core.unrefOpPromise(this.#waitPromise);
     if (this.#stdout) readableStreamForRidUnrefableUnref(this.#stdout);
     if (this.#stderr) readableStreamForRidUnrefableUnref(this.#stderr);
+    if (this.#pty) readableStreamForRidUnrefableUnref(this.#pty.readable);
   }
 }
 
@@ -395,6 +509,13 @@ function spawnSync(command, {
   env = {},
   uid = undefined,
   gid = undefined,
+  rlimits = undefined,
+  processGroup = false,
+  setsid = false,
+  mergeOutput = false,
+  timeout = undefined,
+  killSignal = undefined,
+  killDelay = undefined,
   stdin = "null",
   stdout = "piped",
   stderr = "piped",
@@ -413,6 +534,13 @@ function spawnSync(command, {
     env: ObjectEntries(env),
     uid,
     gid,
+    rlimits,
+    processGroup,
+    setsid,
+    mergeOutput,
+    timeout,
+    killSignal,
+    killDelay,
     stdin,
     stdout,
     stderr,
@@ -422,6 +550,8 @@ function spawnSync(command, {
     success: result.status.success,
     code: result.status.code,
     signal: result.status.signal,
+    timedOut: result.status.timedOut,
+    usage: result.status.usage,
     get stdout() {
       if (result.stdout == null) {
         throw new TypeError("stdout is not piped");
@@ -30,6 +30,7 @@ import * as httpRuntime from "ext:runtime/40_http.js";
 import * as kv from "ext:deno_kv/01_db.ts";
 import * as cron from "ext:deno_cron/01_cron.ts";
 import * as webgpuSurface from "ext:deno_webgpu/02_surface.js";
+import { features } from "ext:runtime/00_features.js";
 
 const denoNs = {
   metrics: () => {
@@ -269,17 +270,37 @@ const denoNsUnstableById = {};
 
 // denoNsUnstableById[unstableIds.broadcastChannel] = {}
 
-denoNsUnstableById[unstableIds.cron] = {
-  cron: cron.cron,
-};
+// Extensions disabled at build time are stubs without exports, their
+// bindings are left out entirely.
+const cronNs = features.cron ? { cron: cron.cron } : {};
 
-denoNsUnstableById[unstableIds.ffi] = {
-  dlopen: ffi.dlopen,
-  UnsafeCallback: ffi.UnsafeCallback,
-  UnsafePointer: ffi.UnsafePointer,
-  UnsafePointerView: ffi.UnsafePointerView,
-  UnsafeFnPointer: ffi.UnsafeFnPointer,
-};
+const ffiNs = features.ffi
+  ? {
+    dlopen: ffi.dlopen,
+    UnsafeCallback: ffi.UnsafeCallback,
+    UnsafePointer: ffi.UnsafePointer,
+    UnsafePointerView: ffi.UnsafePointerView,
+    UnsafeFnPointer: ffi.UnsafeFnPointer,
+  }
+  : {};
+
+const kvNs = features.kv
+  ? {
+    openKv: kv.openKv,
+    AtomicOperation: kv.AtomicOperation,
+    Kv: kv.Kv,
+    KvU64: kv.KvU64,
+    KvListIterator: kv.KvListIterator,
+  }
+  : {};
+
+const webgpuNs = features.webgpu
+  ? { UnsafeWindowSurface: webgpuSurface.UnsafeWindowSurface }
+  : {};
+
+denoNsUnstableById[unstableIds.cron] = cronNs;
+
+denoNsUnstableById[unstableIds.ffi] = ffiNs;
 
 denoNsUnstableById[unstableIds.fs] = {
   flock: fs.flock,
@@ -296,13 +317,7 @@ denoNsUnstableById[unstableIds.http] = {
   http,
 };
 
-denoNsUnstableById[unstableIds.kv] = {
-  openKv: kv.openKv,
-  AtomicOperation: kv.AtomicOperation,
-  Kv: kv.Kv,
-  KvU64: kv.KvU64,
-  KvListIterator: kv.KvListIterator,
-};
+denoNsUnstableById[unstableIds.kv] = kvNs;
 
 denoNsUnstableById[unstableIds.net] = {
   listenDatagram: net.createListenDatagram(
@@ -313,9 +328,7 @@ denoNsUnstableById[unstableIds.net] = {
 
 // denoNsUnstableById[unstableIds.unsafeProto] = {}
 
-denoNsUnstableById[unstableIds.webgpu] = {
-  UnsafeWindowSurface: webgpuSurface.UnsafeWindowSurface,
-};
+denoNsUnstableById[unstableIds.webgpu] = webgpuNs;
 
 // denoNsUnstableById[unstableIds.workerOptions] = {}
 
@@ -330,22 +343,14 @@ const denoNsUnstable = {
   createHttpClient: httpClient.createHttpClient,
   // TODO(bartlomieju): why is it needed?
   http,
-  dlopen: ffi.dlopen,
-  UnsafeCallback: ffi.UnsafeCallback,
-  UnsafePointer: ffi.UnsafePointer,
-  UnsafePointerView: ffi.UnsafePointerView,
-  UnsafeFnPointer: ffi.UnsafeFnPointer,
-  UnsafeWindowSurface: webgpuSurface.UnsafeWindowSurface,
+  ...ffiNs,
+  ...webgpuNs,
   flock: fs.flock,
   flockSync: fs.flockSync,
   funlock: fs.funlock,
   funlockSync: fs.funlockSync,
-  openKv: kv.openKv,
-  AtomicOperation: kv.AtomicOperation,
-  Kv: kv.Kv,
-  KvU64: kv.KvU64,
-  KvListIterator: kv.KvListIterator,
-  cron: cron.cron,
+  ...kvNs,
+  ...cronNs,
 };
 
 export { denoNs, denoNsUnstable, denoNsUnstableById, unstableIds };
@@ -34,6 +34,7 @@ import * as imageData from "ext:deno_web/16_image_data.js";
 import { loadWebGPU } from "ext:deno_webgpu/00_init.js";
 import * as webgpuSurface from "ext:deno_webgpu/02_surface.js";
 import { unstableIds } from "ext:runtime/90_deno_ns.js";
+import { features } from "ext:runtime/00_features.js";
 
 const loadImage = core.createLazyLoader("ext:deno_canvas/01_image.js");
 
@@ -152,8 +153,14 @@ unstableForWindowOrWorkerGlobalScope[unstableIds.broadcastChannel] = {
 unstableForWindowOrWorkerGlobalScope[unstableIds.net] = {
   WebSocketStream: core.propNonEnumerable(webSocketStream.WebSocketStream),
 };
+if (!features.webgpu) {
+  // `deno_canvas` is compiled in together with `deno_webgpu`.
+  delete windowOrWorkerGlobalScope.ImageBitmap;
+  delete windowOrWorkerGlobalScope.createImageBitmap;
+}
+
 // deno-fmt-ignore
-unstableForWindowOrWorkerGlobalScope[unstableIds.webgpu] = {
+unstableForWindowOrWorkerGlobalScope[unstableIds.webgpu] = !features.webgpu ? {} : {
   GPU: core.propNonEnumerableLazyLoaded((webgpu) => webgpu.GPU, loadWebGPU),
   GPUAdapter: core.propNonEnumerableLazyLoaded((webgpu) => webgpu.GPUAdapter, loadWebGPU),
   GPUAdapterInfo: core.propNonEnumerableLazyLoaded((webgpu) => webgpu.GPUAdapterInfo, loadWebGPU),
@@ -19,6 +19,7 @@ import * as globalInterfaces from "ext:deno_web/04_global_interfaces.js";
 import * as webStorage from "ext:deno_webstorage/01_webstorage.js";
 import * as prompt from "ext:runtime/41_prompt.js";
 import { loadWebGPU } from "ext:deno_webgpu/00_init.js";
+import { features } from "ext:runtime/00_features.js";
 
 class Navigator {
   constructor() {
@@ -101,6 +102,9 @@ ObjectDefineProperties(Navigator.prototype, {
     },
   },
 });
+if (!features.webgpu) {
+  delete Navigator.prototype.gpu;
+}
 const NavigatorPrototype = Navigator.prototype;
 
 const mainRuntimeGlobalProperties = {
@@ -17,6 +17,7 @@ import * as console from "ext:deno_console/01_console.js";
 import * as webidl from "ext:deno_webidl/00_webidl.js";
 import * as globalInterfaces from "ext:deno_web/04_global_interfaces.js";
 import { loadWebGPU } from "ext:deno_webgpu/00_init.js";
+import { features } from "ext:runtime/00_features.js";
 
 function memoizeLazy(f) {
   let v_ = null;
@@ -99,6 +100,9 @@ ObjectDefineProperties(WorkerNavigator.prototype, {
     },
   },
 });
+if (!features.webgpu) {
+  delete WorkerNavigator.prototype.gpu;
+}
 const WorkerNavigatorPrototype = WorkerNavigator.prototype;
 
 const workerRuntimeGlobalProperties = {
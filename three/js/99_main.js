@@ -10,10 +10,14 @@ import {
   op_bootstrap_is_tty,
   op_bootstrap_no_color,
   op_bootstrap_pid,
+  op_bootstrap_profile,
   op_main_module,
   op_ppid,
   op_set_format_exception_callback,
   op_snapshot_options,
+  op_virtual_clock_enabled,
+  op_virtual_clock_now,
+  op_virtual_clock_set_timers,
   op_worker_close,
   op_worker_get_type,
   op_worker_post_message,
@@ -27,19 +31,28 @@ const {
   ArrayPrototypePop,
   ArrayPrototypeShift,
   DateNow,
+  DatePrototypeToString,
   Error,
   ErrorPrototype,
   FunctionPrototypeBind,
   FunctionPrototypeCall,
+  MapPrototypeDelete,
+  MapPrototypeSet,
+  MathMax,
   ObjectAssign,
   ObjectDefineProperties,
   ObjectDefineProperty,
+  ObjectFreeze,
   ObjectKeys,
   ObjectPrototypeIsPrototypeOf,
   ObjectSetPrototypeOf,
   ObjectValues,
   PromisePrototypeThen,
   PromiseResolve,
+  ReflectApply,
+  ReflectConstruct,
+  SafeMap,
+  SafeMapIterator,
   SafeSet,
   StringPrototypeIncludes,
   StringPrototypeSplit,
@@ -47,6 +60,7 @@ const {
   Symbol,
   SymbolIterator,
   TypeError,
+  indirectEval,
 } = primordials;
 const {
   isNativeError,
@@ -632,6 +646,158 @@ const {
   target,
 } = op_snapshot_options();
 
+// Applies the embedder's `BootstrapProfile`: disables the deprecated
+// `Deno.run()` if asked to, removes excluded members of the `Deno` namespace
+// and excluded globals, and defines the host's globals as deeply frozen,
+// read-only properties.
+function applyBootstrapProfile(profile) {
+  if (!profile.deprecatedRun) {
+    internals.disableDeprecatedRun();
+  }
+  for (let i = 0; i < profile.denoExclude.length; ++i) {
+    delete finalDenoNs[profile.denoExclude[i]];
+  }
+  for (let i = 0; i < profile.globalExclude.length; ++i) {
+    delete globalThis[profile.globalExclude[i]];
+  }
+  const hostGlobals = ObjectKeys(profile.hostGlobals);
+  for (let i = 0; i < hostGlobals.length; ++i) {
+    const name = hostGlobals[i];
+    ObjectDefineProperty(globalThis, name, {
+      value: deepFreeze(profile.hostGlobals[name]),
+      enumerable: false,
+      configurable: false,
+      writable: false,
+    });
+  }
+}
+
+function deepFreeze(value) {
+  if (typeof value === "object" && value !== null) {
+    const values = ObjectValues(value);
+    for (let i = 0; i < values.length; ++i) {
+      deepFreeze(values[i]);
+    }
+    ObjectFreeze(value);
+  }
+  return value;
+}
+
+// Makes `Date` and `performance.now()` read the host's `VirtualClock` rather
+// than the system time. Returns the time origin for `performance`.
+function installVirtualClock() {
+  const OriginalDate = globalThis.Date;
+  const now = () => op_virtual_clock_now();
+  const Date = function Date(...args) {
+    if (new.target === undefined) {
+      return FunctionPrototypeCall(
+        DatePrototypeToString,
+        new OriginalDate(now()),
+      );
+    }
+    return ReflectConstruct(
+      OriginalDate,
+      args.length === 0 ? [now()] : args,
+      new.target,
+    );
+  };
+  ObjectSetPrototypeOf(Date, OriginalDate);
+  Date.prototype = OriginalDate.prototype;
+  Date.now = now;
+  ObjectDefineProperty(OriginalDate.prototype, "constructor", {
+    value: Date,
+    enumerable: false,
+    configurable: true,
+    writable: true,
+  });
+  ObjectDefineProperty(globalThis, "Date", core.propNonEnumerable(Date));
+
+  const timeOrigin = now();
+  ObjectDefineProperty(performance.Performance.prototype, "now", {
+    value: function now() {
+      return op_virtual_clock_now() - timeOrigin;
+    },
+    enumerable: false,
+    configurable: true,
+    writable: true,
+  });
+  return timeOrigin;
+}
+
+// Replaces the timer globals with timers that fire when the host advances
+// the `VirtualClock` rather than after real time has passed. Only used for
+// the main runtime, workers keep real timers as nothing would advance theirs.
+// The host calls `runDueTimers` until it reports that no timer fired, running
+// the event loop in between so that each callback's microtasks settle before
+// the next one.
+function installVirtualTimers() {
+  const timers = new SafeMap();
+  let nextId = 1;
+
+  function schedule(callback, timeout, args, repeat) {
+    if (typeof callback !== "function") {
+      const code = webidl.converters.DOMString(callback);
+      callback = () => indirectEval(code);
+    }
+    timeout = MathMax(webidl.converters.long(timeout), 0);
+    const id = nextId++;
+    MapPrototypeSet(timers, id, {
+      due: op_virtual_clock_now() + timeout,
+      interval: repeat ? MathMax(timeout, 1) : 0,
+      callback,
+      args,
+    });
+    return id;
+  }
+
+  function clear(id = 0) {
+    MapPrototypeDelete(timers, webidl.converters.long(id));
+  }
+
+  // Fires the earliest timer if it is due, and reports when the next one is.
+  function runDueTimers() {
+    const now = op_virtual_clock_now();
+    let earliestId;
+    let earliest;
+    for (const { 0: id, 1: timer } of new SafeMapIterator(timers)) {
+      if (earliest === undefined || timer.due < earliest.due) {
+        earliestId = id;
+        earliest = timer;
+      }
+    }
+    if (earliest === undefined || earliest.due > now) {
+      return { fired: false, next: earliest?.due };
+    }
+    if (earliest.interval) {
+      earliest.due += earliest.interval;
+    } else {
+      MapPrototypeDelete(timers, earliestId);
+    }
+    try {
+      ReflectApply(earliest.callback, globalThis, earliest.args);
+    } catch (error) {
+      event.reportException(error);
+    }
+    return { fired: true, next: undefined };
+  }
+
+  ObjectDefineProperties(globalThis, {
+    setTimeout: core.propWritable(
+      function setTimeout(callback, timeout = 0, ...args) {
+        return schedule(callback, timeout, args, false);
+      },
+    ),
+    setInterval: core.propWritable(
+      function setInterval(callback, timeout = 0, ...args) {
+        return schedule(callback, timeout, args, true);
+      },
+    ),
+    clearTimeout: core.propWritable(clear),
+    clearInterval: core.propWritable(clear),
+  });
+  op_virtual_clock_set_timers(runDueTimers);
+}
+
 function bootstrapMainRuntime(runtimeOptions) {
   if (hasBootstrapped) {
     throw new Error("Worker runtime already bootstrapped");
@@ -654,7 +820,9 @@ function bootstrapMainRuntime(runtimeOptions) {
 
   deprecatedApiWarningDisabled = shouldDisableDeprecatedApiWarning;
   verboseDeprecatedApiWarning = shouldUseVerboseDeprecatedApiWarning;
-  performance.setTimeOrigin(DateNow());
+  performance.setTimeOrigin(
+    op_virtual_clock_enabled() ? installVirtualClock() : DateNow(),
+  );
   globalThis_ = globalThis;
 
   // Remove bootstrapping data from the global scope
@@ -769,6 +937,12 @@ function bootstrapMainRuntime(runtimeOptions) {
     delete globalThis.Date.prototype.toTemporalInstant;
   }
 
+  if (op_virtual_clock_enabled()) {
+    installVirtualTimers();
+  }
+  const profile = op_bootstrap_profile();
+  applyBootstrapProfile(profile);
+
   // Setup `Deno` global - we're actually overriding already existing global
   // `Deno` with `Deno` namespace from "./deno.ts".
   ObjectDefineProperty(globalThis, "Deno", core.propReadOnly(finalDenoNs));
@@ -777,7 +951,7 @@ function bootstrapMainRuntime(runtimeOptions) {
     nodeBootstrap(hasNodeModulesDir, argv0, /* runningOnMainThread */ true);
   }
 
-  if (future) {
+  if (future || !profile.window) {
     delete globalThis.window;
   }
 }
@@ -806,7 +980,9 @@ function bootstrapWorkerRuntime(
 
   deprecatedApiWarningDisabled = shouldDisableDeprecatedApiWarning;
   verboseDeprecatedApiWarning = shouldUseVerboseDeprecatedApiWarning;
-  performance.setTimeOrigin(DateNow());
+  performance.setTimeOrigin(
+    op_virtual_clock_enabled() ? installVirtualClock() : DateNow(),
+  );
   globalThis_ = globalThis;
 
   removeImportedOps();
@@ -904,6 +1080,8 @@ function bootstrapWorkerRuntime(
       },
     },
   });
+  applyBootstrapProfile(op_bootstrap_profile());
+
   // Setup `Deno` global - we're actually overriding already
   // existing global `Deno` with `Deno` namespace from "./deno.ts".
   ObjectDefineProperty(globalThis, "Deno", core.propReadOnly(finalDenoNs));
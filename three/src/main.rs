@@ -2,7 +2,20 @@ mod permissions;
 mod create_snapshot;
 mod runtime;
 
-fn main() {
-  create_snapshot::create_snapshot();
+use deno_core::anyhow::Context;
+use deno_core::error::AnyError;
+
+/// Usage: `three [bootstrap.js]`. The bootstrap entry module is only
+/// accepted, and then required, with the `exclude_runtime_main_js` feature.
+fn main() -> Result<(), AnyError> {
+  let bootstrap = std::env::args()
+    .nth(1)
+    .map(|path| {
+      std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read bootstrap module {path}"))
+    })
+    .transpose()?;
+  create_snapshot::create_snapshot(bootstrap)?;
   println!("{}", env!("CARGO_MANIFEST_DIR"));
+  Ok(())
 }
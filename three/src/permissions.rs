@@ -39,6 +39,7 @@ impl deno_web::TimersPermission for Permissions {
   }
 }
 
+#[cfg(feature = "ffi")]
 impl deno_ffi::FfiPermissions for Permissions {
   fn check_partial(
     &mut self,
@@ -48,6 +49,7 @@ impl deno_ffi::FfiPermissions for Permissions {
   }
 }
 
+#[cfg(feature = "napi")]
 impl deno_napi::NapiPermissions for Permissions {
   fn check(
     &mut self,
@@ -57,6 +59,7 @@ impl deno_napi::NapiPermissions for Permissions {
   }
 }
 
+#[cfg(feature = "node")]
 impl deno_node::NodePermissions for Permissions {
   fn check_net_url(
     &mut self,
@@ -166,6 +169,7 @@ impl deno_fs::FsPermissions for Permissions {
   }
 }
 
+#[cfg(feature = "kv")]
 impl deno_kv::sqlite::SqliteDbHandlerPermissions for Permissions {
   fn check_read(
     &mut self,
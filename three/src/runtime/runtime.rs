@@ -4,9 +4,11 @@
 use deno_ast::MediaType;
 use deno_ast::ParseParams;
 use deno_ast::SourceTextInfo;
+use deno_core::anyhow::bail;
 use deno_core::error::AnyError;
 use deno_core::extension;
 use deno_core::Extension;
+use deno_core::ExtensionFileSource;
 use deno_core::ModuleCodeString;
 use deno_core::ModuleName;
 use deno_core::SourceMapData;
@@ -55,10 +57,13 @@ extension!(runtime,
     "98_global_scope_worker.js"
   ],
   customizer = |ext: &mut Extension| {
+    ext.esm_files.to_mut().push(ExtensionFileSource::new_computed(
+      "ext:runtime/00_features.js",
+      features_module().into(),
+    ));
     #[cfg(not(feature = "exclude_runtime_main_js"))]
     {
       use deno_core::ascii_str_include;
-      use deno_core::ExtensionFileSource;
       ext.esm_files.to_mut().push(ExtensionFileSource::new("ext:runtime_main/js/99_main.js", ascii_str_include!("../../js/99_main.js")));
       ext.esm_entry_point = Some("ext:runtime_main/js/99_main.js");
     }
@@ -66,6 +71,54 @@ extension!(runtime,
 );
 
 
+/// Specifier of the bootstrap entry supplied by the embedder when
+/// `exclude_runtime_main_js` is enabled.
+pub const BOOTSTRAP_SPECIFIER: &str = "ext:runtime_main/bootstrap.js";
+
+/// The `runtime` extension for creating the snapshot.
+///
+/// By default `99_main.js` is its entry point. With the
+/// `exclude_runtime_main_js` feature the embedder's `bootstrap` module takes
+/// its place and is responsible for setting up the global scope from
+/// `ext:runtime/90_deno_ns.js` and the `98_global_scope_*.js` modules.
+pub fn runtime_extension(
+  bootstrap: Option<String>,
+) -> Result<Extension, AnyError> {
+  let mut ext = runtime::init_ops_and_esm();
+  if cfg!(feature = "exclude_runtime_main_js") {
+    let Some(bootstrap) = bootstrap else {
+      bail!("`exclude_runtime_main_js` requires a bootstrap entry module");
+    };
+    ext.esm_files.to_mut().push(ExtensionFileSource::new_computed(
+      BOOTSTRAP_SPECIFIER,
+      bootstrap.into(),
+    ));
+    ext.esm_entry_point = Some(BOOTSTRAP_SPECIFIER);
+  } else if bootstrap.is_some() {
+    bail!("A bootstrap entry module requires `exclude_runtime_main_js`");
+  }
+  Ok(ext)
+}
+
+/// `ext:runtime/00_features.js`, telling the runtime JS which of the
+/// optional extensions are compiled in. Disabled ones are stubs, see
+/// `host_extensions::extensions`.
+fn features_module() -> String {
+  let features = [
+    ("ffi", cfg!(feature = "ffi")),
+    ("napi", cfg!(feature = "napi")),
+    ("webgpu", cfg!(feature = "webgpu")),
+    ("kv", cfg!(feature = "kv")),
+    ("cron", cfg!(feature = "cron")),
+    ("node", cfg!(feature = "node")),
+  ];
+  let features = features
+    .iter()
+    .map(|(name, enabled)| format!("  {name}: {enabled},\n"))
+    .collect::<String>();
+  format!("export const features = Object.freeze({{\n{features}}});\n")
+}
+
 pub fn maybe_transpile_source(
   name: ModuleName,
   source: ModuleCodeString,